@@ -0,0 +1,681 @@
+use crate::{
+    chunk::Chunk,
+    crypto,
+    decoder::{self, Samples},
+    deflate, fragment,
+    ihdr::ColorType,
+    payload,
+    png::Png,
+    stream::ChunkReader,
+    validate::ViolationKind,
+    Result,
+};
+use std::fmt;
+
+/// Chunk types registered with the PNG specification and its extensions.
+const REGISTERED: [&str; 31] = [
+    "IHDR", "PLTE", "IDAT", "IEND", "tRNS", "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCv",
+    "cLLi", "tEXt", "zTXt", "iTXt", "bKGD", "hIST", "pHYs", "sPLT", "eXIf", "tIME", "acTL", "fcTL",
+    "fdAT", "oFFs", "pCAL", "sCAL", "gIFg", "gIFx", "sTER",
+];
+
+/// Text chunks longer than this are unusual for ordinary metadata.
+const LARGE_TEXT: usize = 1024;
+
+/// Embedding rates below this are within what clean images produce.
+const RATE_THRESHOLD: f64 = 0.05;
+
+/// One reason to suspect the image carries hidden data, scored from 0 (no
+/// evidence) to 100 (certain).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    score: u8,
+    description: String,
+}
+
+impl Finding {
+    fn new(score: u8, description: String) -> Finding {
+        Finding { score, description }
+    }
+
+    pub fn score(&self) -> u8 {
+        self.score
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{:>3}] {}", self.score, self.description)
+    }
+}
+
+/// Statistical tests for LSB embedding run over the pixel samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Statistics {
+    chi_square: f64,
+    rs: f64,
+    spa: f64,
+}
+
+impl Statistics {
+    /// Probability, from the chi-square attack, that the least significant
+    /// bits at the start of the image were replaced.
+    pub fn chi_square(&self) -> f64 {
+        self.chi_square
+    }
+
+    /// Fraction of samples carrying message bits, estimated by RS analysis.
+    pub fn rs(&self) -> f64 {
+        self.rs
+    }
+
+    /// Fraction of samples carrying message bits, estimated by sample pair
+    /// analysis.
+    pub fn spa(&self) -> f64 {
+        self.spa
+    }
+}
+
+/// Everything found while looking for hidden data in an image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    findings: Vec<Finding>,
+    statistics: Option<Statistics>,
+}
+
+impl Report {
+    /// Findings, most suspicious first.
+    pub fn findings(&self) -> &[Finding] {
+        &self.findings
+    }
+
+    /// Results of the pixel tests, if the image format allowed running them.
+    pub fn statistics(&self) -> Option<Statistics> {
+        self.statistics
+    }
+
+    /// Overall score, that of the most suspicious finding.
+    pub fn score(&self) -> u8 {
+        self.findings.iter().map(Finding::score).max().unwrap_or(0)
+    }
+}
+
+/// Analyzes a whole PNG file. Unlike [`Png::try_from`], bytes that cannot be
+/// parsed after `IEND` are reported rather than rejected.
+pub fn analyze_bytes(bytes: &[u8]) -> Result<Report> {
+    let mut chunks = Vec::new();
+    let mut end = Png::STANDARD_HEADER.len();
+    let mut trailing = None;
+    for chunk in ChunkReader::new(bytes)? {
+        match chunk {
+            Ok(chunk) => {
+                end += 12 + chunk.data().len();
+                chunks.push(chunk);
+            }
+            Err(e) if is_after_end(&chunks) => {
+                trailing = Some(e);
+                break;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut report = analyze(&Png::from_chunks(chunks));
+    if trailing.is_some() {
+        report.findings.push(Finding::new(
+            90,
+            format!("{} bytes of data after IEND", bytes.len() - end),
+        ));
+        report
+            .findings
+            .sort_by_key(|finding| std::cmp::Reverse(finding.score));
+    }
+    Ok(report)
+}
+
+fn is_after_end(chunks: &[Chunk]) -> bool {
+    chunks
+        .iter()
+        .any(|chunk| chunk.chunk_type().to_string() == "IEND")
+}
+
+/// Looks for signs of hidden data in the chunk layout, the image data stream
+/// and the pixel statistics of `png`.
+pub fn analyze(png: &Png) -> Report {
+    let mut findings = Vec::new();
+    check_chunks(png, &mut findings);
+    check_image_data(png, &mut findings);
+
+    let statistics = match decoder::decode(png) {
+        Ok(image) if image.ihdr().color_type() != ColorType::Indexed => {
+            let samples: Vec<u8> = match image.samples() {
+                Samples::Eight(samples) if image.ihdr().bit_depth() == 8 => samples.clone(),
+                // The low byte holds the least significant bit.
+                Samples::Sixteen(samples) => samples.iter().map(|&sample| sample as u8).collect(),
+                Samples::Eight(_) => Vec::new(),
+            };
+            (!samples.is_empty()).then(|| statistics(&samples, image.channels(), image.width()))
+        }
+        _ => None,
+    };
+    match statistics {
+        Some(statistics) => check_statistics(&statistics, &mut findings),
+        None => findings.push(Finding::new(
+            0,
+            "Pixel statistics need 8 or 16-bit, non-indexed images".to_string(),
+        )),
+    }
+
+    findings.sort_by_key(|finding| std::cmp::Reverse(finding.score));
+    Report {
+        findings,
+        statistics,
+    }
+}
+
+fn check_chunks(png: &Png, findings: &mut Vec<Finding>) {
+    let mut after_end = false;
+    let mut sizes = Vec::new();
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let chunk_type = chunk.chunk_type();
+        let name = chunk_type.to_string();
+        let describe = |what: &str| {
+            format!(
+                "chunk {} ({}): {}, {} bytes",
+                index,
+                name,
+                what,
+                chunk.length()
+            )
+        };
+
+        if after_end {
+            findings.push(Finding::new(90, describe("after IEND")));
+        }
+        if has_payload_magic(chunk.data()) {
+            findings.push(Finding::new(100, describe("holds a known payload header")));
+        }
+        if !REGISTERED.contains(&name.as_str()) {
+            let finding = match (chunk_type.is_critical(), chunk_type.is_public()) {
+                (true, _) => Finding::new(70, describe("unknown critical chunk")),
+                (false, false) => Finding::new(60, describe("private chunk")),
+                (false, true) => Finding::new(40, describe("unregistered public chunk")),
+            };
+            findings.push(finding);
+        }
+        if matches!(name.as_str(), "tEXt" | "zTXt" | "iTXt") && chunk.data().len() > LARGE_TEXT {
+            findings.push(Finding::new(30, describe("unusually large text")));
+        }
+        match name.as_str() {
+            "IDAT" => sizes.push(chunk.length()),
+            "IEND" => after_end = true,
+            _ => {}
+        }
+    }
+
+    // Encoders split the image data into chunks of one fixed size, with only
+    // the last one shorter.
+    if let Some((_, rest)) = sizes.split_last() {
+        if rest.iter().any(|size| *size != rest[0]) || sizes.contains(&0) {
+            findings.push(Finding::new(
+                30,
+                format!("irregular IDAT chunk sizes: {:?}", sizes),
+            ));
+        }
+    }
+
+    // Chunks after IEND already have a finding of their own above.
+    for violation in png.validate().into_iter().filter(|violation| {
+        !matches!(
+            violation.kind(),
+            ViolationKind::AfterEnd | ViolationKind::NotLast
+        )
+    }) {
+        findings.push(Finding::new(20, violation.to_string()));
+    }
+}
+
+fn has_payload_magic(data: &[u8]) -> bool {
+    [
+        payload::MAGIC,
+        crypto::MAGIC,
+        fragment::MAGIC,
+        deflate::MAGIC,
+    ]
+    .iter()
+    .any(|magic| data.starts_with(magic))
+}
+
+fn check_image_data(png: &Png, findings: &mut Vec<Finding>) {
+    // Image data that does not inflate is already reported by validation.
+    if let Ok(length @ 1..) = deflate::trailing_length(png) {
+        findings.push(Finding::new(
+            95,
+            format!(
+                "{} bytes after the end of the compressed image data",
+                length
+            ),
+        ));
+    }
+}
+
+fn check_statistics(statistics: &Statistics, findings: &mut Vec<Finding>) {
+    let chi_square = statistics.chi_square;
+    if chi_square > 0.5 {
+        findings.push(Finding::new(
+            (chi_square * 100.0).round() as u8,
+            format!(
+                "chi-square attack: least significant bits look replaced (p = {:.3})",
+                chi_square
+            ),
+        ));
+    }
+    for (name, rate) in [
+        ("RS analysis", statistics.rs),
+        ("sample pair analysis", statistics.spa),
+    ] {
+        if rate > RATE_THRESHOLD {
+            findings.push(Finding::new(
+                rate_score(rate),
+                format!("{}: estimated embedding rate {:.1}%", name, rate * 100.0),
+            ));
+        }
+    }
+}
+
+/// Maps an estimated embedding rate to a score, reaching 100 at a rate of 45%.
+fn rate_score(rate: f64) -> u8 {
+    ((rate - RATE_THRESHOLD) * 250.0).clamp(0.0, 100.0).round() as u8
+}
+
+/// Runs all pixel tests over interleaved 8-bit samples of `channels`
+/// channels, `width` pixels per row.
+fn statistics(samples: &[u8], channels: usize, width: usize) -> Statistics {
+    let planes: Vec<Vec<u8>> = (0..channels)
+        .map(|channel| {
+            samples
+                .iter()
+                .skip(channel)
+                .step_by(channels)
+                .copied()
+                .collect()
+        })
+        .collect();
+    let mean = |f: &dyn Fn(&[u8]) -> f64| {
+        planes.iter().map(|plane| f(plane)).sum::<f64>() / channels as f64
+    };
+
+    // Sequential embedding starts at the top of the image, so the first tenth
+    // is tested on its own as well.
+    let head = &samples[..(samples.len() / 10).max(samples.len().min(1024))];
+    Statistics {
+        chi_square: chi_square(samples).max(chi_square(head)),
+        rs: mean(&rs_analysis),
+        spa: mean(&|plane| sample_pair_analysis(plane, width)),
+    }
+}
+
+/// The chi-square attack of Westfeld and Pfitzmann. Replacing least
+/// significant bits with message bits evens out the counts of each pair of
+/// values 2k and 2k+1; returns the probability that the observed counts are
+/// that even by chance.
+fn chi_square(samples: &[u8]) -> f64 {
+    let mut histogram = [0u64; 256];
+    for &sample in samples {
+        histogram[sample as usize] += 1;
+    }
+
+    let mut statistic = 0.0;
+    let mut categories = 0;
+    for pair in histogram.chunks_exact(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected > 4.0 {
+            statistic += (pair[0] as f64 - expected).powi(2) / expected;
+            categories += 1;
+        }
+    }
+    if categories < 2 {
+        return 0.0;
+    }
+    1.0 - regularized_gamma((categories - 1) as f64 / 2.0, statistic / 2.0)
+}
+
+/// RS analysis of Fridrich, Goljan and Du, over groups of four consecutive
+/// samples of one channel. Returns the estimated fraction of samples whose
+/// least significant bit carries message bits.
+fn rs_analysis(samples: &[u8]) -> f64 {
+    let (d0, dn0) = rs_differences(samples, false);
+    let (d1, dn1) = rs_differences(samples, true);
+
+    let a = 2.0 * (d1 + d0);
+    let b = dn0 - dn1 - d1 - 3.0 * d0;
+    let c = d0 - dn0;
+    let discriminant = b * b - 4.0 * a * c;
+    let x = if a.abs() > f64::EPSILON && discriminant >= 0.0 {
+        let roots = [
+            (-b + discriminant.sqrt()) / (2.0 * a),
+            (-b - discriminant.sqrt()) / (2.0 * a),
+        ];
+        if roots[0].abs() < roots[1].abs() {
+            roots[0]
+        } else {
+            roots[1]
+        }
+    } else if b.abs() > f64::EPSILON && discriminant >= 0.0 {
+        -c / b
+    } else {
+        f64::NAN
+    };
+    let estimate = x / (x - 0.5);
+    if (0.0..=1.0).contains(&estimate) || x.abs() < 0.25 {
+        return estimate.clamp(0.0, 1.0);
+    }
+    // Near full embedding the mask stops changing smoothness on average and
+    // the quadratic degenerates. How far the positive mask difference has
+    // collapsed relative to the negative one still tells the rate.
+    if dn0 > 0.0 {
+        (1.0 - d0 / dn0).clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+/// Differences between the fraction of regular and singular groups for the
+/// mask and the negative mask, optionally after flipping every least
+/// significant bit first.
+fn rs_differences(samples: &[u8], flip: bool) -> (f64, f64) {
+    const MASK: [bool; 4] = [false, true, true, false];
+    let smoothness =
+        |group: &[i32; 4]| -> i32 { group.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum() };
+    let positive = |x: i32| x ^ 1;
+    let negative = |x: i32| ((x + 1) ^ 1) - 1;
+
+    let (mut regular, mut singular) = ([0u64; 2], [0u64; 2]);
+    let mut groups = 0;
+    for group in samples.chunks_exact(4) {
+        let group = [0, 1, 2, 3].map(|i| (if flip { group[i] ^ 1 } else { group[i] }) as i32);
+        let before = smoothness(&group);
+        for (i, flipping) in [&positive as &dyn Fn(i32) -> i32, &negative]
+            .into_iter()
+            .enumerate()
+        {
+            let flipped = [0, 1, 2, 3].map(|i| {
+                if MASK[i] {
+                    flipping(group[i])
+                } else {
+                    group[i]
+                }
+            });
+            let after = smoothness(&flipped);
+            if after > before {
+                regular[i] += 1;
+            } else if after < before {
+                singular[i] += 1;
+            }
+        }
+        groups += 1;
+    }
+    if groups == 0 {
+        return (0.0, 0.0);
+    }
+    let fraction = |count: u64| count as f64 / groups as f64;
+    (
+        fraction(regular[0]) - fraction(singular[0]),
+        fraction(regular[1]) - fraction(singular[1]),
+    )
+}
+
+/// Sample pair analysis of Dumitrescu, Wu and Wang, over horizontally
+/// adjacent samples of one channel. Returns the estimated fraction of samples
+/// whose least significant bit carries message bits.
+fn sample_pair_analysis(samples: &[u8], width: usize) -> f64 {
+    let (mut x, mut y, mut k, mut pairs) = (0u64, 0u64, 0u64, 0u64);
+    for row in samples.chunks(width.max(1)) {
+        for pair in row.windows(2) {
+            let (r, s) = (pair[0], pair[1]);
+            if (s % 2 == 0 && r < s) || (s % 2 == 1 && r > s) {
+                x += 1;
+            }
+            if (s % 2 == 0 && r > s) || (s % 2 == 1 && r < s) {
+                y += 1;
+            }
+            if r / 2 == s / 2 {
+                k += 1;
+            }
+            pairs += 1;
+        }
+    }
+    if k == 0 {
+        return 0.0;
+    }
+
+    let a = 2.0 * k as f64;
+    let b = 2.0 * (2.0 * x as f64 - pairs as f64);
+    let c = y as f64 - x as f64;
+    // Near full embedding the roots become complex; their real part is then
+    // the closest estimate.
+    let discriminant = (b * b - 4.0 * a * c).max(0.0);
+    let beta = (-b - discriminant.sqrt()) / (2.0 * a);
+    // Beta is the fraction of modified samples, half of those carrying
+    // message bits.
+    (2.0 * beta).clamp(0.0, 1.0)
+}
+
+/// The regularized lower incomplete gamma function P(a, x), which gives the
+/// chi-square distribution function.
+fn regularized_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let prefix = (a * x.ln() - x - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        // Series expansion.
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        for _ in 0..1000 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        (sum * prefix).min(1.0)
+    } else {
+        // Continued fraction for the upper function, evaluated with Lentz's
+        // method.
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < 1e-15 {
+                break;
+            }
+        }
+        (1.0 - prefix * h).max(0.0)
+    }
+}
+
+/// Natural logarithm of the gamma function, by the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.001_208_650_973_866_179,
+        -0.000_005_395_239_384_953,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series: f64 = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .map(|(i, coefficient)| coefficient / (x + 1.0 + i as f64))
+        .sum();
+    -tmp + (2.506_628_274_631_000_5 * (1.000_000_000_190_015 + series) / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunk_type::ChunkType,
+        ihdr::Ihdr,
+        lsb::{self, LsbOptions},
+    };
+    use std::str::FromStr;
+
+    const PNG_FILE: &[u8] = include_bytes!("../image/dice.png");
+
+    fn testing_png() -> Png {
+        Png::try_from(PNG_FILE).unwrap()
+    }
+
+    /// Deterministic pseudorandom message bytes.
+    fn noise(length: usize) -> Vec<u8> {
+        let mut state: u32 = 0x2545_f491;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_image() {
+        let report = analyze(&testing_png());
+        let statistics = report.statistics().unwrap();
+        assert!(statistics.rs() < RATE_THRESHOLD, "{:?}", statistics);
+        assert!(statistics.spa() < RATE_THRESHOLD, "{:?}", statistics);
+        assert!(statistics.chi_square() < 0.5, "{:?}", statistics);
+        assert!(report.score() < 50, "{:?}", report);
+    }
+
+    #[test]
+    fn test_full_lsb_embedding() {
+        let mut png = testing_png();
+        let capacity = lsb::capacity(&png, &LsbOptions::default()).unwrap();
+        lsb::embed(&mut png, &noise(capacity), &LsbOptions::default()).unwrap();
+
+        let report = analyze(&png);
+        let statistics = report.statistics().unwrap();
+        assert!(statistics.rs() > 0.5, "{:?}", statistics);
+        assert!(statistics.spa() > 0.5, "{:?}", statistics);
+        assert!(statistics.chi_square() > 0.9, "{:?}", statistics);
+        assert!(report.score() >= 90);
+    }
+
+    #[test]
+    fn test_partial_lsb_embedding() {
+        let mut png = testing_png();
+        let capacity = lsb::capacity(&png, &LsbOptions::default()).unwrap();
+        lsb::embed(&mut png, &noise(capacity / 5), &LsbOptions::default()).unwrap();
+
+        let report = analyze(&png);
+        assert!(report.score() >= 50, "{:?}", report);
+    }
+
+    #[test]
+    fn test_private_chunk() {
+        let mut png = testing_png();
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hello".to_vec());
+        png.insert_chunk_before("IEND", chunk).unwrap();
+        let report = analyze(&png);
+        assert_eq!(report.score(), 60);
+        assert!(report.findings()[0].description().contains("private chunk"));
+    }
+
+    #[test]
+    fn test_payload_header() {
+        let mut png = testing_png();
+        let data = crypto::encrypt(b"password", b"hello").unwrap();
+        let chunk = Chunk::new(ChunkType::from_str("tEXt").unwrap(), data);
+        png.insert_chunk_before("IEND", chunk).unwrap();
+        assert_eq!(analyze(&png).score(), 100);
+    }
+
+    #[test]
+    fn test_deflate_trailer() {
+        let mut png = testing_png();
+        deflate::embed(&mut png, b"hello").unwrap();
+        let report = analyze(&png);
+        assert!(report.score() >= 95);
+    }
+
+    #[test]
+    fn test_data_after_end() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend(b"appended");
+        let report = analyze_bytes(&bytes).unwrap();
+        assert_eq!(report.score(), 90);
+        assert_eq!(
+            report.findings()[0].description(),
+            "8 bytes of data after IEND"
+        );
+
+        let mut png = testing_png();
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("tEXt").unwrap(),
+            b"a\0b".to_vec(),
+        ));
+        let report = analyze(&png);
+        assert_eq!(report.score(), 90);
+        let ordering = report
+            .findings()
+            .iter()
+            .filter(|finding| finding.description().contains("IEND"))
+            .count();
+        assert_eq!(ordering, 1, "{:?}", report);
+
+        assert!(analyze_bytes(&PNG_FILE[..100]).is_err());
+    }
+
+    #[test]
+    fn test_irregular_image_data() {
+        let mut png = testing_png();
+        let data = png.image_data();
+        let (first, rest) = data.split_at(1000);
+        let ihdr: Ihdr = png.ihdr().unwrap();
+        let chunks = vec![
+            ihdr.as_chunk(),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), first.to_vec()),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), rest.to_vec()),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), Vec::new()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ];
+        png = Png::from_chunks(chunks);
+        assert_eq!(analyze(&png).score(), 30);
+    }
+
+    #[test]
+    fn test_regularized_gamma() {
+        // Chi-square distribution function with 2 degrees of freedom is
+        // 1 - e^(-x/2).
+        for x in [0.5, 2.0, 10.0] {
+            let expected = 1.0 - (-x / 2.0f64).exp();
+            assert!((regularized_gamma(1.0, x / 2.0) - expected).abs() < 1e-9);
+        }
+        assert!((ln_gamma(5.0) - 24.0f64.ln()).abs() < 1e-9);
+    }
+}
@@ -1,4 +1,6 @@
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::str::FromStr;
+use steganography::{fragment, lsb, ChunkType};
 
 #[derive(Parser)]
 pub struct Cli {
@@ -8,42 +10,282 @@ pub struct Cli {
 
 #[derive(Subcommand)]
 pub enum Commands {
+    /// Hide a message or file in an image.
     Encode(EncodeArgs),
+    /// Recover a message hidden with encode.
     Decode(DecodeArgs),
+    /// Remove chunks of a type from an image.
     Remove(RemoveArgs),
+    /// List the chunks of an image.
     Print(PrintArgs),
+    /// Check an image against the rules of the PNG specification.
+    Validate(ValidateArgs),
+    /// Show how many bytes each mode can hide in an image.
+    Capacity(CapacityArgs),
+    /// Look for signs of hidden data and score how likely the image has any.
+    Analyze(AnalyzeArgs),
 }
 
 #[derive(Args)]
 pub struct EncodeArgs {
     #[clap(value_parser)]
     pub file_path: String,
-    #[clap(value_parser)]
-    pub chunk_type: String,
-    #[clap(value_parser)]
-    pub message: String,
+    /// Type of the chunk holding the message. Use auto to pick a private type
+    /// the image does not use yet.
+    #[clap(value_parser = parse_message_chunk_type)]
+    pub chunk_type: MessageChunkType,
+    #[clap(value_parser, required_unless_present = "input_file")]
+    pub message: Option<String>,
     #[clap(value_parser)]
     pub output_path: Option<String>,
+    /// Hide the contents of this file instead of a message. Use - to read stdin.
+    #[clap(long, value_parser, conflicts_with = "message")]
+    pub input_file: Option<String>,
+    /// Where to write the image. Same as the output path argument, for use
+    /// with --input-file.
+    #[clap(short, long, value_parser, conflicts_with = "output_path")]
+    pub output: Option<String>,
+    #[clap(long, value_enum, default_value_t = Mode::Chunk)]
+    pub mode: Mode,
+    #[clap(flatten)]
+    pub lsb: LsbArgs,
+    /// Where the message chunk is inserted.
+    #[clap(long, value_enum, default_value_t = Position::BeforeIend)]
+    pub position: Position,
+    /// With an auto chunk type, prefer names real software writes.
+    #[clap(long)]
+    pub mimic: bool,
+    /// Allow hiding the message in a critical chunk type, which may stop the
+    /// image from displaying.
+    #[clap(long)]
+    pub force: bool,
+    #[clap(flatten)]
+    pub format: FormatArgs,
+    /// Largest number of message bytes per chunk. Longer messages are split
+    /// across several chunks of the same type.
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u32).range(1..),
+        default_value_t = fragment::DEFAULT_SIZE as u32
+    )]
+    pub fragment_size: u32,
+    /// How to compress the message before hiding it.
+    #[clap(long, value_enum, default_value_t = Compress::None)]
+    pub compress: Compress,
+    #[clap(flatten)]
+    pub secret: SecretArgs,
 }
 
 #[derive(Args)]
 pub struct DecodeArgs {
     #[clap(value_parser)]
     pub file_path: String,
-    #[clap(value_parser)]
-    pub chunk_type: String,
+    /// Type of the chunks holding the message. Only used in chunk mode.
+    #[clap(
+        value_parser = parse_chunk_type,
+        required_unless_present = "mode",
+        required_if_eq("mode", "chunk")
+    )]
+    pub chunk_type: Option<ChunkType>,
+    #[clap(long, value_enum, default_value_t = Mode::Chunk)]
+    pub mode: Mode,
+    #[clap(flatten)]
+    pub lsb: LsbArgs,
+    #[clap(flatten)]
+    pub format: FormatArgs,
+    /// Write the message to this file instead of printing it. Use - for raw
+    /// stdout, or a directory to restore the file under its original name.
+    #[clap(long, value_parser)]
+    pub output_file: Option<String>,
+    /// Extract every message of the chunk type instead of only the first.
+    #[clap(long)]
+    pub all: bool,
+    /// Replace existing files when restoring into a directory given with
+    /// --output-file.
+    #[clap(long)]
+    pub overwrite: bool,
+    #[clap(flatten)]
+    pub secret: SecretArgs,
 }
 
 #[derive(Args)]
 pub struct RemoveArgs {
     #[clap(value_parser)]
     pub file_path: String,
-    #[clap(value_parser)]
-    pub chunk_type: String,
+    #[clap(value_parser = parse_chunk_type)]
+    pub chunk_type: ChunkType,
+    /// Remove every chunk of the type instead of only the first.
+    #[clap(long)]
+    pub all: bool,
+    /// Remove only the chunk of the type at this index, counting from 0.
+    #[clap(long, value_parser, conflicts_with = "all")]
+    pub index: Option<usize>,
 }
 
 #[derive(Args)]
 pub struct PrintArgs {
     #[clap(value_parser)]
     pub file_path: String,
+    #[clap(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+#[derive(Args)]
+pub struct ValidateArgs {
+    #[clap(value_parser)]
+    pub file_path: String,
+    /// Fail if any violation is found.
+    #[clap(long)]
+    pub strict: bool,
+}
+
+#[derive(Args)]
+pub struct CapacityArgs {
+    #[clap(value_parser)]
+    pub file_path: String,
+    /// Show LSB capacity when hiding this many bits in every 2^K-1 carrier
+    /// bits with a Hamming code, as encode --code does.
+    #[clap(long, value_name = "K", value_parser = clap::value_parser!(u8).range(1..=lsb::MAX_CODE as i64), default_value_t = 1)]
+    pub code: u8,
+}
+
+#[derive(Args)]
+pub struct AnalyzeArgs {
+    #[clap(value_parser)]
+    pub file_path: String,
+}
+
+/// Chunk type argument of `encode`.
+#[derive(Clone)]
+pub enum MessageChunkType {
+    /// Pick a covert type the image does not use yet.
+    Auto,
+    Named(ChunkType),
+}
+
+/// Parses a chunk type argument, rejecting types no PNG may contain.
+fn parse_chunk_type(value: &str) -> Result<ChunkType, String> {
+    let chunk_type = ChunkType::from_str(value).map_err(|e| e.to_string())?;
+    if !chunk_type.is_reserved_bit_valid() {
+        let mut fixed = chunk_type.clone();
+        fixed.set_reserved_bit_valid(true);
+        return Err(format!(
+            "the third letter of a chunk type must be uppercase, lowercase is reserved (try {})",
+            fixed
+        ));
+    }
+    Ok(chunk_type)
+}
+
+fn parse_message_chunk_type(value: &str) -> Result<MessageChunkType, String> {
+    match value {
+        "auto" => Ok(MessageChunkType::Auto),
+        value => parse_chunk_type(value).map(MessageChunkType::Named),
+    }
+}
+
+/// How `print` lists the chunks.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One line per chunk.
+    Text,
+    /// A JSON array with one object per chunk.
+    Json,
+}
+
+/// Where the message is hidden inside the image.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Mode {
+    /// In a chunk of the given type.
+    Chunk,
+    /// In the least significant bits of the pixel samples. The chunk type is ignored.
+    Lsb,
+    /// In pairs of near-identical colors of an indexed-color image, leaving
+    /// the palette as it is. The chunk type is ignored.
+    Palette,
+    /// After the end of the compressed image data, leaving the pixels as they
+    /// are. The chunk type is ignored.
+    Deflate,
+}
+
+/// Where the message chunk is placed among the existing chunks.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Position {
+    /// Right before IEND, after the image data.
+    BeforeIend,
+    /// Between IHDR and the first IDAT.
+    BeforeIdat,
+    /// After IEND. Some decoders and sanitizers flag or strip such chunks.
+    AfterIend,
+}
+
+// How the message is spread over the pixels in LSB mode.
+#[derive(Args)]
+pub struct LsbArgs {
+    /// Number of low bits of every sample that carry the message. Must match
+    /// when decoding.
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..=16), default_value_t = 1)]
+    pub bits: u8,
+    /// Spread the message over the whole image in an order derived from the
+    /// password or key file, instead of filling it from the top. Must match
+    /// when decoding.
+    #[clap(long, requires = "SecretArgs")]
+    pub scatter: bool,
+    /// Hide this many bits in every 2^K-1 carrier bits with a Hamming code,
+    /// changing at most one of them. Higher values change fewer pixels but
+    /// hold less. Must match when decoding.
+    #[clap(long, value_name = "K", value_parser = clap::value_parser!(u8).range(1..=lsb::MAX_CODE as i64), default_value_t = 1)]
+    pub code: u8,
+}
+
+// How the message chunks are written in chunk mode.
+#[derive(Args)]
+pub struct FormatArgs {
+    /// Store the message as the given kind of chunk. Text formats ignore the
+    /// chunk type and look like ordinary metadata.
+    #[clap(long = "as", value_enum, default_value_t = Format::Raw)]
+    pub format: Format,
+    /// Keyword of the text chunks holding the message.
+    #[clap(long, value_parser, default_value = "Comment")]
+    pub keyword: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// The message bytes, in a chunk of the given type.
+    Raw,
+    /// A tEXt chunk. Text messages are stored as is; encrypted, compressed,
+    /// file and fragmented messages, and messages under a keyword the image
+    /// already uses, are Base64 encoded with a header.
+    Text,
+    /// A compressed zTXt chunk, stored like tEXt.
+    Ztxt,
+    /// An iTXt chunk, stored like tEXt.
+    Itxt,
+}
+
+/// Compression applied to the message. Zstd and Brotli need the matching
+/// cargo feature.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compress {
+    None,
+    Deflate,
+    Zstd,
+    Brotli,
+    /// Whichever available algorithm gives the smallest result, or none if
+    /// compressing does not help.
+    Auto,
+}
+
+// Secret used to encrypt or decrypt the hidden message. A doc comment here
+// would replace the help text of every subcommand that flattens it, as it
+// would on the other flattened argument groups.
+#[derive(Args)]
+pub struct SecretArgs {
+    /// Password to derive the encryption key from.
+    #[clap(long, value_parser, conflicts_with = "key_file")]
+    pub password: Option<String>,
+    /// File whose contents are used to derive the encryption key.
+    #[clap(long, value_parser)]
+    pub key_file: Option<String>,
 }
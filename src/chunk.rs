@@ -1,5 +1,5 @@
 use crate::{chunk_type::ChunkType, Error};
-use std::{convert::TryFrom, fmt::Formatter, mem, path::Display};
+use std::{convert::TryFrom, fmt::Formatter};
 
 #[derive(Debug)]
 pub struct Chunk {
@@ -9,19 +9,19 @@ pub struct Chunk {
 }
 
 impl Chunk {
+    /// Largest data length allowed by the PNG specification.
+    pub const MAX_LENGTH: u32 = (1 << 31) - 1;
+
     pub fn length(&self) -> u32 {
         self.data.len() as u32
     }
 
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Self {
         let crc_generator = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
-        let crc_data: Vec<u8> = chunk_type
-            .bytes()
-            .iter()
-            .chain(data.iter())
-            .copied()
-            .collect();
-        let crc = crc_generator.checksum(&crc_data);
+        let mut digest = crc_generator.digest();
+        digest.update(&chunk_type.bytes());
+        digest.update(&data);
+        let crc = digest.finalize();
 
         Chunk {
             chunk_type,
@@ -61,36 +61,38 @@ impl TryFrom<&[u8]> for Chunk {
 
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
         // Get Fields.
-        let length = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
-        let chunk_type = ChunkType::try_from([bytes[4], bytes[5], bytes[6], bytes[7]])?;
-        let data = bytes[8..bytes.len() - 4].to_vec();
-        let crc = u32::from_be_bytes([
-            bytes[bytes.len() - 4],
-            bytes[bytes.len() - 3],
-            bytes[bytes.len() - 2],
-            bytes[bytes.len() - 1],
-        ]);
+        if bytes.len() < 12 {
+            return Err(Error::TruncatedChunk { offset: 0 });
+        }
+        let (header, rest) = bytes.split_at(8);
+        let (data, crc) = rest.split_at(rest.len() - 4);
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let chunk_type = ChunkType::try_from([header[4], header[5], header[6], header[7]])?;
+        let crc = u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]);
 
         // Validate Data Length.
-        if data.len() as u32 != length {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Invalid data length",
-            )));
+        if length > Chunk::MAX_LENGTH {
+            return Err(Error::ChunkTooLong { length, offset: 0 });
+        }
+        if data.len() as u64 != length as u64 {
+            return Err(Error::TruncatedChunk { offset: 0 });
         }
 
         // Validate CRC.
         let crc_generator = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
-        if crc_generator.checksum(&bytes[4..bytes.len() - 4]) != crc {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Invalid CRC",
-            )));
+        let expected = crc_generator.checksum(&bytes[4..bytes.len() - 4]);
+        if expected != crc {
+            return Err(Error::CrcMismatch {
+                chunk_type,
+                expected,
+                actual: crc,
+                offset: 0,
+            });
         }
 
         Ok(Chunk {
             chunk_type,
-            data,
+            data: data.to_vec(),
             crc,
         })
     }
@@ -98,7 +100,7 @@ impl TryFrom<&[u8]> for Chunk {
 
 impl std::fmt::Display for Chunk {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.data_as_string().unwrap())
+        write!(f, "{}", String::from_utf8_lossy(&self.data))
     }
 }
 
@@ -208,7 +210,14 @@ mod tests {
 
         let chunk = Chunk::try_from(chunk_data.as_ref());
 
-        assert!(chunk.is_err());
+        assert!(matches!(
+            chunk,
+            Err(Error::CrcMismatch {
+                expected: 2882656334,
+                actual: 2882656333,
+                ..
+            })
+        ));
     }
 
     #[test]
@@ -1,13 +1,22 @@
 #![allow(unused_variables)]
 
-use std::{
-    io::{self, Bytes},
-    str::FromStr,
-};
+use std::str::FromStr;
+
+use rand::Rng;
 
 use crate::Error;
 
-#[derive(Debug, PartialEq, Eq)]
+/// Private, ancillary, safe-to-copy chunk types written by real-world
+/// software, which draw less attention than random letters.
+pub const VENDOR_TYPES: [&str; 5] = [
+    "npTc", // Android nine-patch padding
+    "npLb", // Android nine-patch layout bounds
+    "npOl", // Android nine-patch outline
+    "vpAg", // ImageMagick virtual page
+    "caNv", // ImageMagick canvas
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ChunkType {
     bytes: [u8; 4],
 }
@@ -37,9 +46,63 @@ impl ChunkType {
         !self.is_uppercase(3)
     }
 
+    pub fn set_critical(&mut self, critical: bool) {
+        self.set_uppercase(0, critical);
+    }
+
+    pub fn set_public(&mut self, public: bool) {
+        self.set_uppercase(1, public);
+    }
+
+    pub fn set_reserved_bit_valid(&mut self, valid: bool) {
+        self.set_uppercase(2, valid);
+    }
+
+    pub fn set_safe_to_copy(&mut self, safe_to_copy: bool) {
+        self.set_uppercase(3, !safe_to_copy);
+    }
+
+    /// Picks a valid, ancillary, private, safe-to-copy type for which
+    /// `is_taken` is false. With `mimic`, the first free type from
+    /// [`VENDOR_TYPES`] is preferred over random letters.
+    pub fn covert<F>(mimic: bool, is_taken: F) -> ChunkType
+    where
+        F: Fn(&ChunkType) -> bool,
+    {
+        let vendor = VENDOR_TYPES
+            .iter()
+            .map(|name| ChunkType::from_str(name).unwrap())
+            .find(|chunk_type| !is_taken(chunk_type));
+        if let (true, Some(chunk_type)) = (mimic, vendor) {
+            return chunk_type;
+        }
+
+        let mut rng = rand::thread_rng();
+        loop {
+            let mut chunk_type = ChunkType {
+                bytes: [0; 4].map(|_| rng.gen_range(b'a'..=b'z')),
+            };
+            chunk_type.set_critical(false);
+            chunk_type.set_public(false);
+            chunk_type.set_reserved_bit_valid(true);
+            chunk_type.set_safe_to_copy(true);
+            if !is_taken(&chunk_type) {
+                return chunk_type;
+            }
+        }
+    }
+
     fn is_uppercase(&self, index: usize) -> bool {
         self.bytes[index] >= 65 && self.bytes[index] <= 90
     }
+
+    fn set_uppercase(&mut self, index: usize, uppercase: bool) {
+        if uppercase {
+            self.bytes[index].make_ascii_uppercase();
+        } else {
+            self.bytes[index].make_ascii_lowercase();
+        }
+    }
 }
 
 impl std::convert::TryFrom<[u8; 4]> for ChunkType {
@@ -48,10 +111,9 @@ impl std::convert::TryFrom<[u8; 4]> for ChunkType {
     fn try_from(bytes: [u8; 4]) -> Result<Self, Self::Error> {
         for byte in &bytes {
             if !byte.is_ascii_alphabetic() {
-                return Err(Box::new(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "Invalid chunk type. Byte needs to be an alphabet.",
-                )));
+                return Err(Error::InvalidChunkType(
+                    String::from_utf8_lossy(&bytes).into_owned(),
+                ));
             }
         }
         Ok(ChunkType { bytes })
@@ -63,19 +125,13 @@ impl FromStr for ChunkType {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if s.len() != 4 {
-            return Err(Box::new(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Invalid chunk type. Length needs to be 4.",
-            )));
+            return Err(Error::InvalidChunkType(s.to_string()));
         }
 
         let mut bytes = [0; 4];
         for (i, byte) in s.bytes().enumerate() {
             if !byte.is_ascii_alphabetic() {
-                return Err(Box::new(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "Invalid chunk type. Byte needs to be an alphabet.",
-                )));
+                return Err(Error::InvalidChunkType(s.to_string()));
             }
             bytes[i] = byte;
         }
@@ -174,6 +230,45 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_chunk_type_setters() {
+        let mut chunk = ChunkType::from_str("RuSt").unwrap();
+        chunk.set_critical(false);
+        chunk.set_public(true);
+        chunk.set_safe_to_copy(false);
+        assert_eq!(chunk.to_string(), "rUST");
+        chunk.set_reserved_bit_valid(false);
+        assert!(!chunk.is_valid());
+        chunk.set_reserved_bit_valid(true);
+        chunk.set_safe_to_copy(true);
+        assert_eq!(chunk.to_string(), "rUSt");
+    }
+
+    #[test]
+    pub fn test_covert_chunk_type() {
+        for _ in 0..100 {
+            let chunk = ChunkType::covert(false, |chunk| chunk.to_string() == "abCd");
+            assert!(chunk.is_valid());
+            assert!(!chunk.is_critical());
+            assert!(!chunk.is_public());
+            assert!(chunk.is_safe_to_copy());
+        }
+
+        let chunk = ChunkType::covert(true, |chunk| chunk.to_string() == VENDOR_TYPES[0]);
+        assert_eq!(chunk.to_string(), VENDOR_TYPES[1]);
+        let chunk = ChunkType::covert(true, |chunk| VENDOR_TYPES.contains(&&*chunk.to_string()));
+        assert!(!VENDOR_TYPES.contains(&&*chunk.to_string()));
+    }
+
+    #[test]
+    pub fn test_vendor_types_are_covert() {
+        for name in VENDOR_TYPES {
+            let chunk = ChunkType::from_str(name).unwrap();
+            assert!(chunk.is_valid() && !chunk.is_critical() && !chunk.is_public());
+            assert!(chunk.is_safe_to_copy());
+        }
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -1,50 +1,563 @@
-use crate::args::{DecodeArgs, EncodeArgs, RemoveArgs};
-use crate::chunk::Chunk;
-use crate::chunk_type::ChunkType;
-use crate::png::Png;
-use crate::Result;
-use std::fs;
+use crate::args::{
+    AnalyzeArgs, CapacityArgs, Compress, DecodeArgs, EncodeArgs, Format, LsbArgs, MessageChunkType,
+    Mode, OutputFormat, Position, PrintArgs, RemoveArgs, SecretArgs, ValidateArgs,
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use steganography::analyze;
+use steganography::compression::Compression;
+use steganography::crypto;
+use steganography::deflate;
+use steganography::fragment;
+use steganography::inspect;
+use steganography::lsb::{self, LsbOptions};
+use steganography::palette;
+use steganography::payload::{self, Payload};
+use steganography::png::{Placement, Png};
+use steganography::stream::{ChunkReader, ChunkWriter};
+use steganography::text::{self, CompressedText, InternationalText, Text, TextChunk};
+use steganography::{Chunk, ChunkType, Error, Result};
 
 fn get_png(file_path: &str) -> Result<Png> {
-    let file = fs::read(file_path)?;
-    Ok(Png::try_from(&file[..])?)
+    let reader = ChunkReader::new(BufReader::new(File::open(file_path)?))?;
+    Ok(Png::from_chunks(reader.collect::<Result<_>>()?))
 }
 
-pub fn encode(args: &EncodeArgs) -> Result<()> {
-    let mut png = get_png(&args.file_path)?;
-    let chunk_type_bytes: [u8; 4] = args.chunk_type.as_bytes().try_into().unwrap();
-    let chunk = Chunk::new(
-        ChunkType::try_from(chunk_type_bytes)?,
-        args.message.as_bytes().to_vec(),
-    );
-    png.append_chunk(chunk);
-    let output_path = match &args.output_path {
-        Some(path) => path,
-        None => &args.file_path,
-    };
-    fs::write(output_path, png.as_bytes())?;
+fn write_png(file_path: &str, png: &Png) -> Result<()> {
+    let mut writer = ChunkWriter::new(BufWriter::new(File::create(file_path)?))?;
+    for chunk in png.chunks() {
+        writer.write_chunk(chunk)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Streams the chunks of `input_path` into `output_path` through `rewrite`,
+/// which is called once per chunk and a final time with `None`. The output is
+/// written to a temporary file first, so both paths may be the same file.
+fn rewrite_png<F>(input_path: &str, output_path: &str, mut rewrite: F) -> Result<()>
+where
+    F: FnMut(Option<Chunk>, &mut ChunkWriter<BufWriter<File>>) -> Result<()>,
+{
+    let reader = ChunkReader::new(BufReader::new(File::open(input_path)?))?;
+    let (temp_path, temp_file) = create_temp_file(output_path)?;
+    let result = (|| {
+        let mut writer = ChunkWriter::new(BufWriter::new(temp_file))?;
+        for chunk in reader {
+            rewrite(Some(chunk?), &mut writer)?;
+        }
+        rewrite(None, &mut writer)?;
+        writer.finish()?;
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => Ok(fs::rename(&temp_path, output_path)?),
+        Err(e) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+/// Creates a new, uniquely named file next to `output_path`, so it can be
+/// renamed over it. Existing files are never touched, and concurrent runs
+/// each get their own.
+fn create_temp_file(output_path: &str) -> Result<(PathBuf, File)> {
+    let output_path = Path::new(output_path);
+    let dir = output_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let name = output_path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    loop {
+        let temp_path = dir.join(format!(".{}.{:08x}.tmp", name, rand::random::<u32>()));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+fn get_secret(args: &SecretArgs) -> Result<Option<Vec<u8>>> {
+    match (&args.password, &args.key_file) {
+        (Some(password), _) => Ok(Some(password.as_bytes().to_vec())),
+        (None, Some(key_file)) => Ok(Some(fs::read(key_file)?)),
+        (None, None) => Ok(None),
+    }
+}
+
+fn lsb_options(args: &LsbArgs, secret: Option<&[u8]>) -> Result<LsbOptions> {
+    // Clap makes sure a secret comes with --scatter.
+    let key = match secret {
+        Some(secret) if args.scatter => Some(crypto::derive_seed(secret)?),
+        _ => None,
+    };
+    Ok(LsbOptions {
+        bits: args.bits,
+        key,
+        code: args.code,
+    })
+}
+
+fn get_payload(args: &EncodeArgs) -> Result<Payload> {
+    match args.input_file.as_deref() {
+        Some("-") => {
+            let mut data = Vec::new();
+            io::stdin().read_to_end(&mut data)?;
+            Ok(Payload::new(data))
+        }
+        Some(path) => Payload::from_file(path, fs::read(path)?),
+        None => Ok(Payload::new(
+            args.message.clone().unwrap_or_default().into_bytes(),
+        )),
+    }
+}
+
+/// Writes an extracted payload to `path`: stdout for -, or the original file
+/// name, else `name`, inside `path` if it is a directory. The name may come
+/// from a crafted image, so files in the directory are only replaced with
+/// `overwrite`.
+fn save_payload(path: &str, payload: &Payload, name: &str, overwrite: bool) -> Result<()> {
+    if path == "-" {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(payload.data())?;
+        return Ok(stdout.flush()?);
+    }
+    let path = Path::new(path);
+    if path.is_dir() {
+        let file_path = path.join(payload.file_name().unwrap_or(name));
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .create_new(!overwrite)
+            .open(&file_path);
+        let mut file = match file {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(Error::Io(io::Error::new(
+                    e.kind(),
+                    format!(
+                        "{} already exists. Use --overwrite to replace it.",
+                        file_path.display()
+                    ),
+                )))
+            }
+            file => file?,
+        };
+        file.write_all(payload.data())?;
+    } else {
+        fs::write(path, payload.data())?;
+    }
     Ok(())
 }
 
+/// The type of the chunks holding the message. For auto, a covert type the
+/// image does not use yet is picked and reported.
+fn message_chunk_type(args: &EncodeArgs) -> Result<ChunkType> {
+    let name = match (args.format.format, &args.chunk_type) {
+        (Format::Raw, MessageChunkType::Auto) => {
+            let chunk_type = get_png(&args.file_path)?.unused_chunk_type(args.mimic);
+            eprintln!("Hiding the message in {} chunks.", chunk_type);
+            return Ok(chunk_type);
+        }
+        (Format::Raw, MessageChunkType::Named(chunk_type)) => {
+            if chunk_type.is_critical() && !args.force {
+                return Err(Error::CriticalChunkType(chunk_type.clone()));
+            }
+            return Ok(chunk_type.clone());
+        }
+        (Format::Text, _) => "tEXt",
+        (Format::Ztxt, _) => "zTXt",
+        (Format::Itxt, _) => "iTXt",
+    };
+    ChunkType::from_str(name)
+}
+
+/// Wraps one fragment of the message in a chunk of the requested format.
+fn message_chunk(args: &EncodeArgs, chunk_type: &ChunkType, data: Vec<u8>) -> Result<Chunk> {
+    let keyword = &args.format.keyword;
+    match args.format.format {
+        Format::Raw => Ok(Chunk::new(chunk_type.clone(), data)),
+        Format::Text => Ok(Text::new(keyword, &text::payload_to_text(&data)?)?.as_chunk()),
+        Format::Ztxt => CompressedText::new(keyword, &text::payload_to_text(&data)?)?.as_chunk(),
+        Format::Itxt => {
+            let text = text::payload_to_text(&data)?;
+            InternationalText::new(keyword, "", "", &text, false)?.as_chunk()
+        }
+    }
+}
+
+/// Returns true if the image has a text chunk with `keyword`.
+fn has_keyword(png: &Png, keyword: &str) -> bool {
+    png.chunks()
+        .iter()
+        .filter_map(|chunk| TextChunk::try_from(chunk).ok())
+        .any(|text| text.keyword() == keyword)
+}
+
+/// The contents of every chunk that may hold part of the message, in file
+/// order. Text formats match text chunks with the keyword, keeping only those
+/// with a payload header when there are any: a plain message is only written
+/// under a keyword no other text uses, and gets a header otherwise.
+fn message_pieces(png: &Png, args: &DecodeArgs) -> Vec<Vec<u8>> {
+    match args.format.format {
+        Format::Raw => png
+            .chunks()
+            .iter()
+            .filter(|chunk| Some(chunk.chunk_type()) == args.chunk_type.as_ref())
+            .map(|chunk| chunk.data().to_vec())
+            .collect(),
+        Format::Text | Format::Ztxt | Format::Itxt => {
+            let mut pieces: Vec<Vec<u8>> = png
+                .chunks()
+                .iter()
+                .filter_map(|chunk| TextChunk::try_from(chunk).ok())
+                .filter(|text| text.keyword() == args.format.keyword)
+                .map(|text| text::text_to_payload(text.text()))
+                .collect();
+            if pieces.iter().any(|piece| text::has_magic(piece)) {
+                pieces.retain(|piece| text::has_magic(piece));
+            }
+            pieces
+        }
+    }
+}
+
+pub fn encode(args: &EncodeArgs) -> Result<()> {
+    let mut payload = get_payload(args)?;
+    let text_format = args.mode == Mode::Chunk && args.format.format != Format::Raw;
+    if text_format && has_keyword(&get_png(&args.file_path)?, &args.format.keyword) {
+        // Plain text could not be told apart from the text already there.
+        payload.set_header_required(true);
+        eprintln!(
+            "Warning: the image already has text with the keyword {}, so the \
+             message is stored Base64 encoded with a header to tell it apart. \
+             Use another --keyword to keep it readable.",
+            args.format.keyword
+        );
+    }
+    let compression = match args.compress {
+        Compress::None => Some(Compression::None),
+        Compress::Deflate => Some(Compression::Deflate),
+        Compress::Zstd => Some(Compression::Zstd),
+        Compress::Brotli => Some(Compression::Brotli),
+        Compress::Auto => None,
+    };
+    let payload = match compression {
+        Some(compression) => {
+            payload.set_compression(compression);
+            payload.as_bytes()?
+        }
+        None => payload.as_smallest_bytes()?,
+    };
+    let secret = get_secret(&args.secret)?;
+    let data = match &secret {
+        Some(secret) => crypto::encrypt(secret, &payload)?,
+        None => payload,
+    };
+    let output_path = args
+        .output_path
+        .as_ref()
+        .or(args.output.as_ref())
+        .unwrap_or(&args.file_path);
+    match args.mode {
+        Mode::Chunk => {
+            let chunk_type = message_chunk_type(args)?;
+            let mut chunks = fragment::split(&data, args.fragment_size as usize)?
+                .into_iter()
+                .map(|data| message_chunk(args, &chunk_type, data))
+                .collect::<Result<Vec<Chunk>>>()?;
+            let placement = match args.position {
+                Position::BeforeIend => Placement::BeforeEnd,
+                Position::BeforeIdat => Placement::BeforeImageData,
+                Position::AfterIend => Placement::End,
+            };
+            rewrite_png(&args.file_path, output_path, |next, writer| {
+                let is_anchor = next
+                    .as_ref()
+                    .is_none_or(|next| placement.is_anchor(next.chunk_type()));
+                if is_anchor {
+                    for chunk in chunks.drain(..) {
+                        writer.write_chunk(&chunk)?;
+                    }
+                }
+                match next {
+                    Some(next) => writer.write_chunk(&next),
+                    None => Ok(()),
+                }
+            })
+        }
+        Mode::Lsb => {
+            let mut png = get_png(&args.file_path)?;
+            let options = lsb_options(&args.lsb, secret.as_deref())?;
+            let embedding = lsb::embed(&mut png, &data, &options)?;
+            eprintln!("{}", embedding);
+            write_png(output_path, &png)
+        }
+        Mode::Palette => {
+            let mut png = get_png(&args.file_path)?;
+            palette::embed(&mut png, &data)?;
+            write_png(output_path, &png)
+        }
+        Mode::Deflate => {
+            let mut png = get_png(&args.file_path)?;
+            deflate::embed(&mut png, &data)?;
+            write_png(output_path, &png)
+        }
+    }
+}
+
 pub fn decode(args: &DecodeArgs) -> Result<()> {
     let png = get_png(&args.file_path)?;
-    if let Some(chunk) = png.chunk_by_type(&args.chunk_type) {
-        println!("{}", chunk.data_as_string()?);
+    let secret = get_secret(&args.secret)?;
+    if args.all {
+        if args.mode != Mode::Chunk {
+            return Err(Error::Unsupported("--all only applies to chunk mode."));
+        }
+        return decode_all(&png, args, secret.as_deref());
+    }
+
+    let data = match args.mode {
+        Mode::Chunk => {
+            let pieces = message_pieces(&png, args);
+            fragment::join(pieces.iter().map(Vec::as_slice))?
+        }
+        Mode::Lsb => {
+            let options = lsb_options(&args.lsb, secret.as_deref())?;
+            Some(lsb::extract(&png, &options)?)
+        }
+        Mode::Palette => Some(palette::extract(&png)?),
+        Mode::Deflate => Some(deflate::extract(&png)?),
+    };
+    if let Some(data) = data {
+        let payload = open_payload(data, secret.as_deref())?;
+        show_payload(args, &payload, "message")?;
     }
     Ok(())
 }
 
+/// Extracts every message in chunks of the requested type. A message that
+/// cannot be read is reported and skipped; the first such error is returned
+/// once the others have been extracted.
+fn decode_all(png: &Png, args: &DecodeArgs, secret: Option<&[u8]>) -> Result<()> {
+    if let Some(path) = args.output_file.as_deref() {
+        if path != "-" && !Path::new(path).is_dir() {
+            return Err(Error::Unsupported(
+                "--all needs a directory or - as the output file.",
+            ));
+        }
+    }
+
+    let pieces = message_pieces(png, args);
+    let messages = fragment::join_all(pieces.iter().map(Vec::as_slice));
+    if messages.is_empty() {
+        return Err(match (args.format.format, &args.chunk_type) {
+            (Format::Raw, Some(chunk_type)) => Error::ChunkNotFound(chunk_type.to_string()),
+            (Format::Raw, None) => Error::MessageNotFound,
+            _ => Error::ChunkNotFound(format!("with the keyword {}", args.format.keyword)),
+        });
+    }
+    let mut first_error = None;
+    for (index, data) in messages.into_iter().enumerate() {
+        eprintln!("Message {}:", index);
+        let result = data.and_then(|data| {
+            // Messages hidden without encryption are shown as they are.
+            let secret = secret.filter(|_| crypto::is_encrypted(&data));
+            let payload = open_payload(data, secret)?;
+            show_payload(args, &payload, &format!("message-{}", index))
+        });
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
+            first_error.get_or_insert(e);
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+/// Decrypts extracted data if needed and parses its payload header.
+fn open_payload(data: Vec<u8>, secret: Option<&[u8]>) -> Result<Payload> {
+    let data = match (secret, crypto::is_encrypted(&data)) {
+        (Some(secret), true) => crypto::decrypt(secret, &data)?,
+        (None, false) => data,
+        (None, true) => return Err(Error::SecretRequired),
+        (Some(_), false) => return Err(Error::NotEncrypted),
+    };
+    Payload::try_from(&data[..])
+}
+
+/// Saves the payload to the `--output-file` if given, using `name` for a plain
+/// message restored into a directory, or prints it otherwise.
+fn show_payload(args: &DecodeArgs, payload: &Payload, name: &str) -> Result<()> {
+    match args.output_file.as_deref() {
+        Some(path) => save_payload(path, payload, name, args.overwrite),
+        None => {
+            if let (Some(file_name), Some(mime_type)) = (payload.file_name(), payload.mime_type()) {
+                eprintln!("Message is the file {} ({}).", file_name, mime_type);
+            }
+            println!("{}", String::from_utf8(payload.data().to_vec())?);
+            Ok(())
+        }
+    }
+}
+
 pub fn remove(args: &RemoveArgs) -> Result<()> {
-    let mut png = get_png(&args.file_path)?;
-    let _chunk = png.remove_first_chunk(&args.chunk_type)?;
-    fs::write(&args.file_path, png.as_bytes())?;
+    let mut seen = 0;
+    let mut removed = 0;
+    rewrite_png(
+        &args.file_path,
+        &args.file_path,
+        |next, writer| match next {
+            Some(chunk) if chunk.chunk_type() == &args.chunk_type => {
+                let matches = match args.index {
+                    Some(index) => seen == index,
+                    None => args.all || removed == 0,
+                };
+                seen += 1;
+                if matches {
+                    removed += 1;
+                    Ok(())
+                } else {
+                    writer.write_chunk(&chunk)
+                }
+            }
+            Some(chunk) => writer.write_chunk(&chunk),
+            None if removed == 0 => Err(Error::ChunkNotFound(match args.index {
+                Some(index) => format!("{} at index {}", args.chunk_type, index),
+                None => args.chunk_type.to_string(),
+            })),
+            None => {
+                eprintln!("Removed {} chunk(s).", removed);
+                Ok(())
+            }
+        },
+    )
+}
+
+pub fn print(args: &PrintArgs) -> Result<()> {
+    let png = get_png(&args.file_path)?;
+    let chunks = inspect::inspect(&png);
+    match args.format {
+        OutputFormat::Text => {
+            if let Ok(ihdr) = png.ihdr() {
+                println!("{}", ihdr);
+            }
+            println!(
+                "{:>10}  type  {:>10}  crc       flags  summary",
+                "offset", "length"
+            );
+            for chunk in &chunks {
+                println!("{}", chunk);
+            }
+        }
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(&chunks).map_err(io::Error::from)?;
+            println!("{}", json);
+        }
+    }
     Ok(())
 }
 
-pub fn print(file_path: &str) -> Result<()> {
-    let png = get_png(file_path)?;
-    for chunk in png.chunks() {
-        println!("{}", chunk);
+pub fn validate(args: &ValidateArgs) -> Result<()> {
+    let png = get_png(&args.file_path)?;
+    let violations = png.validate();
+    for violation in &violations {
+        println!("{}", violation);
+    }
+    if violations.is_empty() {
+        println!("No problems found.");
+    } else if args.strict {
+        return Err(Error::InvalidStructure(violations));
+    }
+    Ok(())
+}
+
+pub fn capacity(args: &CapacityArgs) -> Result<()> {
+    let png = get_png(&args.file_path)?;
+    let ihdr = png.ihdr()?;
+    println!("{}", ihdr);
+
+    let mut modes = vec![("chunk".to_string(), Ok(None))];
+    for bits in 1..=ihdr.bit_depth() {
+        let mut name = format!("lsb --bits {}", bits);
+        if args.code > 1 {
+            name.push_str(&format!(" --code {}", args.code));
+        }
+        let options = LsbOptions {
+            bits,
+            code: args.code,
+            ..LsbOptions::default()
+        };
+        modes.push((name, lsb::capacity(&png, &options).map(Some)));
+    }
+    modes.push(("palette".to_string(), palette::capacity(&png).map(Some)));
+    modes.push(("deflate".to_string(), deflate::capacity(&png)));
+
+    // Largest payload in bytes after each kind of header is added, or None
+    // if there is no limit.
+    let overheads = [
+        ("plain", 0),
+        ("compressed", payload::MIN_HEADER_LENGTH),
+        ("encrypted", crypto::OVERHEAD),
+        ("both", payload::MIN_HEADER_LENGTH + crypto::OVERHEAD),
+    ];
+    let width = modes
+        .iter()
+        .map(|(name, _)| name.len() + 2)
+        .max()
+        .unwrap_or(0);
+    print!("{:<width$}", "mode");
+    for (name, _) in overheads {
+        print!("{:>12}", name);
+    }
+    println!();
+    for (name, capacity) in modes {
+        print!("{:<width$}", name);
+        match capacity {
+            Ok(Some(capacity)) => {
+                for (_, overhead) in overheads {
+                    print!("{:>12}", capacity.saturating_sub(overhead));
+                }
+                println!();
+            }
+            Ok(None) => println!("{:>12}", "unlimited"),
+            Err(e) => println!("  {}", e),
+        }
+    }
+    println!("Files also store their name and MIME type in the header.");
+    Ok(())
+}
+
+pub fn analyze(args: &AnalyzeArgs) -> Result<()> {
+    let report = analyze::analyze_bytes(&fs::read(&args.file_path)?)?;
+    for finding in report.findings() {
+        println!("{}", finding);
     }
+    if let Some(statistics) = report.statistics() {
+        println!(
+            "chi-square p = {:.3}, RS rate = {:.1}%, SPA rate = {:.1}%",
+            statistics.chi_square(),
+            statistics.rs() * 100.0,
+            statistics.spa() * 100.0
+        );
+    }
+    let verdict = match report.score() {
+        0..=29 => "no sign of hidden data",
+        30..=69 => "suspicious",
+        _ => "likely holds hidden data",
+    };
+    println!("Score: {}/100, {}", report.score(), verdict);
     Ok(())
 }
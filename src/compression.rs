@@ -0,0 +1,191 @@
+use crate::{Error, Result};
+use std::{
+    fmt,
+    io::{Read, Write},
+};
+
+/// Largest payload [`Compression::decompress`] produces.
+pub const MAX_DECOMPRESSED_LENGTH: usize = 1 << 30;
+
+/// Algorithm used to compress a payload before it is hidden. Zstandard and
+/// Brotli need the `zstd` and `brotli` cargo features; payloads using them can
+/// still be recognized without the feature, but not decompressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None = 0,
+    Deflate = 1,
+    Zstd = 2,
+    Brotli = 3,
+}
+
+impl Compression {
+    /// Every algorithm that actually compresses, whether available or not.
+    pub const ALL: [Compression; 3] =
+        [Compression::Deflate, Compression::Zstd, Compression::Brotli];
+
+    /// Returns true if this build can compress and decompress with the algorithm.
+    pub fn is_available(&self) -> bool {
+        match self {
+            Compression::None | Compression::Deflate => true,
+            Compression::Zstd => cfg!(feature = "zstd"),
+            Compression::Brotli => cfg!(feature = "brotli"),
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Deflate => {
+                let mut encoder =
+                    flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Ok(zstd::encode_all(data, 19)?),
+            #[cfg(feature = "brotli")]
+            Compression::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 11, 22);
+                encoder.write_all(data)?;
+                Ok(encoder.into_inner())
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(Error::CompressionUnavailable(*self)),
+        }
+    }
+
+    /// Decompresses a payload of at most [`MAX_DECOMPRESSED_LENGTH`] bytes.
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        self.decompress_with_limit(data, MAX_DECOMPRESSED_LENGTH)
+    }
+
+    /// Decompresses `data`, failing with [`Error::CorruptCompression`] as soon
+    /// as the output grows past `limit` bytes, so a small crafted payload
+    /// cannot exhaust memory.
+    pub fn decompress_with_limit(&self, data: &[u8], limit: usize) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Deflate => read_limited(flate2::read::DeflateDecoder::new(data), limit),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                let decoder = zstd::stream::read::Decoder::new(data)
+                    .map_err(|_| Error::CorruptCompression)?;
+                read_limited(decoder, limit)
+            }
+            #[cfg(feature = "brotli")]
+            Compression::Brotli => read_limited(brotli::Decompressor::new(data, 4096), limit),
+            #[allow(unreachable_patterns)]
+            _ => Err(Error::CompressionUnavailable(*self)),
+        }
+    }
+}
+
+/// Reads all of `reader`, which must produce at most `limit` bytes.
+fn read_limited(reader: impl Read, limit: usize) -> Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|_| Error::CorruptCompression)?;
+    if decompressed.len() > limit {
+        return Err(Error::CorruptCompression);
+    }
+    Ok(decompressed)
+}
+
+impl TryFrom<u8> for Compression {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Deflate),
+            2 => Ok(Compression::Zstd),
+            3 => Ok(Compression::Brotli),
+            _ => Err(Error::InvalidHeader("Unknown compression algorithm.")),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Compression::None => "none",
+            Compression::Deflate => "deflate",
+            Compression::Zstd => "zstd",
+            Compression::Brotli => "brotli",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data() -> Vec<u8> {
+        b"all work and no play makes jack a dull boy. ".repeat(50)
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for compression in Compression::ALL
+            .into_iter()
+            .filter(Compression::is_available)
+        {
+            let compressed = compression.compress(&data()).unwrap();
+            assert!(compressed.len() < data().len() / 4, "{}", compression);
+            assert_eq!(compression.decompress(&compressed).unwrap(), data());
+        }
+    }
+
+    #[test]
+    fn test_unavailable() {
+        for compression in Compression::ALL.into_iter().filter(|c| !c.is_available()) {
+            assert!(matches!(
+                compression.compress(&data()),
+                Err(Error::CompressionUnavailable(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_decompression_limit() {
+        for compression in Compression::ALL
+            .into_iter()
+            .filter(Compression::is_available)
+        {
+            let compressed = compression.compress(&data()).unwrap();
+            let length = data().len();
+            assert_eq!(
+                compression
+                    .decompress_with_limit(&compressed, length)
+                    .unwrap(),
+                data()
+            );
+            assert!(matches!(
+                compression.decompress_with_limit(&compressed, length - 1),
+                Err(Error::CorruptCompression)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_corrupt() {
+        assert!(matches!(
+            Compression::Deflate.decompress(&[0xff; 16]),
+            Err(Error::CorruptCompression)
+        ));
+    }
+
+    #[test]
+    fn test_from_u8() {
+        for compression in Compression::ALL {
+            assert_eq!(
+                Compression::try_from(compression as u8).unwrap(),
+                compression
+            );
+        }
+        assert!(Compression::try_from(4).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+use crate::{Error, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::{rngs::OsRng, RngCore};
+
+/// Marks chunk data produced by [`encrypt`].
+pub const MAGIC: [u8; 4] = *b"StEn";
+pub const VERSION: u8 = 1;
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+const KEY_LENGTH: usize = 32;
+const TAG_LENGTH: usize = 16;
+
+/// Argon2id cost parameters of VERSION 1. They are part of the format: keys
+/// derived with any other values cannot decrypt existing images, so changing
+/// them needs a new VERSION.
+const ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+const ARGON2_ITERATIONS: u32 = 2;
+const ARGON2_PARALLELISM: u32 = 1;
+
+/// Fixed salt for [`derive_seed`]. The seed must be derivable from the
+/// secret alone, since there is nowhere to store a random salt before the
+/// hidden data is located.
+const SEED_SALT: &[u8] = b"steganography pixel order";
+
+/// Header layout: magic (4) | version (1) | salt (16) | nonce (24).
+/// The ciphertext follows, with the 16 byte Poly1305 tag at the very end.
+pub const HEADER_LENGTH: usize = MAGIC.len() + 1 + SALT_LENGTH + NONCE_LENGTH;
+
+/// Number of bytes [`encrypt`] adds to the plaintext.
+pub const OVERHEAD: usize = HEADER_LENGTH + TAG_LENGTH;
+
+/// Returns true if `data` starts with an encrypted payload header.
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Encrypts `plaintext` with a key derived from `secret` using Argon2id and
+/// XChaCha20-Poly1305. The header is authenticated along with the ciphertext.
+pub fn encrypt(secret: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut salt = [0; SALT_LENGTH];
+    let mut nonce = [0; NONCE_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut header = Vec::with_capacity(HEADER_LENGTH + plaintext.len() + TAG_LENGTH);
+    header.extend_from_slice(&MAGIC);
+    header.push(VERSION);
+    header.extend_from_slice(&salt);
+    header.extend_from_slice(&nonce);
+
+    let cipher = XChaCha20Poly1305::new(&derive_key(secret, &salt)?.into());
+    let ciphertext = cipher
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: &header,
+            },
+        )
+        .map_err(|_| Error::InvalidPayload("Payload is too large to encrypt."))?;
+
+    header.extend(ciphertext);
+    Ok(header)
+}
+
+/// Decrypts data produced by [`encrypt`]. Fails if the secret is wrong or the
+/// data has been tampered with.
+pub fn decrypt(secret: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < HEADER_LENGTH + TAG_LENGTH || !is_encrypted(data) {
+        return Err(Error::InvalidPayload("Header is missing."));
+    }
+    if data[MAGIC.len()] != VERSION {
+        return Err(Error::InvalidPayload("Unsupported version."));
+    }
+
+    let (header, ciphertext) = data.split_at(HEADER_LENGTH);
+    let salt = &header[MAGIC.len() + 1..MAGIC.len() + 1 + SALT_LENGTH];
+    let nonce = &header[HEADER_LENGTH - NONCE_LENGTH..];
+
+    let cipher = XChaCha20Poly1305::new(&derive_key(secret, salt)?.into());
+    cipher
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| Error::DecryptionFailed)
+}
+
+/// Derives a 32 byte seed from `secret` with Argon2id, for keying the order
+/// in which pixels carry a message. The same secret always gives the same
+/// seed, and it is unrelated to the key [`encrypt`] derives.
+pub fn derive_seed(secret: &[u8]) -> Result<[u8; KEY_LENGTH]> {
+    derive_key(secret, SEED_SALT)
+}
+
+fn derive_key(secret: &[u8], salt: &[u8]) -> Result<[u8; KEY_LENGTH]> {
+    let params = Params::new(
+        ARGON2_MEMORY_KIB,
+        ARGON2_ITERATIONS,
+        ARGON2_PARALLELISM,
+        Some(KEY_LENGTH),
+    )
+    .map_err(|_| Error::KeyDerivation)?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+    let mut key = [0; KEY_LENGTH];
+    argon2
+        .hash_password_into(secret, salt, &mut key)
+        .map_err(|_| Error::KeyDerivation)?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let data = encrypt(b"hunter2", b"This is a secret").unwrap();
+        assert!(is_encrypted(&data));
+        assert_eq!(data.len(), HEADER_LENGTH + 16 + TAG_LENGTH);
+        assert_eq!(decrypt(b"hunter2", &data).unwrap(), b"This is a secret");
+    }
+
+    #[test]
+    fn test_ciphertext_hides_plaintext() {
+        let data = encrypt(b"hunter2", b"This is a secret").unwrap();
+        assert!(!data.windows(6).any(|window| window == b"secret"));
+    }
+
+    #[test]
+    fn test_key_derivation_is_pinned() {
+        // Changing this key makes every image encrypted with VERSION 1
+        // impossible to decrypt.
+        assert_eq!(
+            derive_key(b"hunter2", &[7; SALT_LENGTH]).unwrap(),
+            [
+                0x60, 0xac, 0x90, 0x9c, 0xe0, 0xb9, 0x57, 0x55, 0x46, 0x05, 0x45, 0x22, 0xcb, 0x7f,
+                0x9c, 0x62, 0x0d, 0x40, 0x09, 0x4c, 0x06, 0x3e, 0x73, 0xfa, 0x54, 0xc4, 0x60, 0x47,
+                0x4b, 0xdd, 0x05, 0x60
+            ]
+        );
+    }
+
+    #[test]
+    fn test_derive_seed() {
+        let seed = derive_seed(b"hunter2").unwrap();
+        assert_eq!(derive_seed(b"hunter2").unwrap(), seed);
+        assert_ne!(derive_seed(b"hunter3").unwrap(), seed);
+    }
+
+    #[test]
+    fn test_wrong_password() {
+        let data = encrypt(b"hunter2", b"This is a secret").unwrap();
+        assert!(matches!(
+            decrypt(b"hunter3", &data),
+            Err(Error::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_tampered_payload() {
+        let mut data = encrypt(b"hunter2", b"This is a secret").unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        assert!(decrypt(b"hunter2", &data).is_err());
+
+        let mut data = encrypt(b"hunter2", b"This is a secret").unwrap();
+        data[MAGIC.len() + 1] ^= 1;
+        assert!(decrypt(b"hunter2", &data).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let mut data = encrypt(b"hunter2", b"This is a secret").unwrap();
+        data[MAGIC.len()] = VERSION + 1;
+        assert!(decrypt(b"hunter2", &data).is_err());
+    }
+
+    #[test]
+    fn test_plain_data_is_not_encrypted() {
+        assert!(!is_encrypted(b"This is where your secret message will be!"));
+        assert!(decrypt(b"hunter2", b"StEn").is_err());
+    }
+}
@@ -0,0 +1,605 @@
+use crate::{
+    ihdr::{ColorType, Ihdr, InterlaceMethod},
+    png::Png,
+    Error, Result,
+};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::io::{Read, Write};
+
+/// Adam7 passes as (first column, first row, column step, row step).
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// One reduced image stored in the image data: the whole image, or one of
+/// the Adam7 passes. Pixel (i, j) of the pass is pixel
+/// (x + i * dx, y + j * dy) of the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Pass {
+    pub x: usize,
+    pub y: usize,
+    pub dx: usize,
+    pub dy: usize,
+    pub width: usize,
+    pub height: usize,
+    /// Length of one scanline in bytes, not counting the filter type byte.
+    pub stride: usize,
+}
+
+/// The passes of the image in storage order. Empty passes, which have no
+/// scanlines in the data, are left out.
+pub(crate) fn passes(ihdr: &Ihdr) -> Vec<Pass> {
+    let (width, height) = (ihdr.width() as usize, ihdr.height() as usize);
+    let layout: &[_] = match ihdr.interlace_method() {
+        InterlaceMethod::None => &[(0, 0, 1, 1)],
+        InterlaceMethod::Adam7 => &ADAM7,
+    };
+    layout
+        .iter()
+        .map(|&(x, y, dx, dy)| {
+            let pass_width = (width + dx - 1 - x) / dx;
+            Pass {
+                x,
+                y,
+                dx,
+                dy,
+                width: pass_width,
+                height: (height + dy - 1 - y) / dy,
+                stride: (pass_width * ihdr.bits_per_pixel()).div_ceil(8),
+            }
+        })
+        .filter(|pass| pass.width > 0 && pass.height > 0)
+        .collect()
+}
+
+/// Unfiltered scanlines of every pass, one after the other, along with the
+/// filter type each scanline was originally stored with.
+pub(crate) struct Scanlines {
+    pub filters: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+impl Scanlines {
+    pub fn decode(png: &Png, ihdr: &Ihdr) -> Result<Scanlines> {
+        let passes = passes(ihdr);
+        let expected = passes
+            .iter()
+            .try_fold(0usize, |total, pass| {
+                (pass.stride + 1)
+                    .checked_mul(pass.height)
+                    .and_then(|size| total.checked_add(size))
+            })
+            .ok_or(Error::Unsupported("Image is too large."))?;
+
+        // Never inflate more than the header allows, so a small stream cannot
+        // expand into an arbitrarily large buffer.
+        let compressed = png.image_data();
+        let mut filtered = Vec::new();
+        ZlibDecoder::new(&compressed[..])
+            .take(expected as u64 + 1)
+            .read_to_end(&mut filtered)
+            .map_err(|_| Error::InvalidImageData("Corrupt zlib stream."))?;
+
+        if filtered.len() != expected {
+            return Err(Error::InvalidImageData(
+                "Image data does not match the header.",
+            ));
+        }
+
+        let distance = ihdr.bytes_per_pixel();
+        let mut filters = Vec::new();
+        let mut data = Vec::with_capacity(expected);
+        let mut lines = &filtered[..];
+        for pass in passes {
+            for row in 0..pass.height {
+                let (line, rest) = lines.split_at(pass.stride + 1);
+                lines = rest;
+                let start = data.len();
+                data.extend_from_slice(&line[1..]);
+                let (previous, current) = data.split_at_mut(start);
+                let previous = (row > 0).then(|| &previous[start - pass.stride..]);
+                unfilter(line[0], distance, current, previous)?;
+                filters.push(line[0]);
+            }
+        }
+
+        Ok(Scanlines { filters, data })
+    }
+
+    pub fn encode(&self, ihdr: &Ihdr) -> Result<Vec<u8>> {
+        let distance = ihdr.bytes_per_pixel();
+        let mut filtered = Vec::with_capacity(self.data.len() + self.filters.len());
+        let mut filters = self.filters.iter();
+        let mut start = 0;
+        for pass in passes(ihdr) {
+            for row in 0..pass.height {
+                let current = &self.data[start..start + pass.stride];
+                let previous = (row > 0).then(|| &self.data[start - pass.stride..start]);
+                let filter_type = *filters.next().unwrap_or(&0);
+                filtered.push(filter_type);
+                filtered.extend(filter(filter_type, distance, current, previous));
+                start += pass.stride;
+            }
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&filtered)?;
+        Ok(encoder.finish()?)
+    }
+}
+
+pub(crate) fn unfilter(
+    filter_type: u8,
+    distance: usize,
+    current: &mut [u8],
+    previous: Option<&[u8]>,
+) -> Result<()> {
+    for i in 0..current.len() {
+        let left = if i >= distance {
+            current[i - distance]
+        } else {
+            0
+        };
+        let up = previous.map_or(0, |previous| previous[i]);
+        let upper_left = match previous {
+            Some(previous) if i >= distance => previous[i - distance],
+            _ => 0,
+        };
+        current[i] = current[i].wrapping_add(match filter_type {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => paeth(left, up, upper_left),
+            _ => return Err(Error::InvalidImageData("Unknown filter type.")),
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn filter(
+    filter_type: u8,
+    distance: usize,
+    current: &[u8],
+    previous: Option<&[u8]>,
+) -> Vec<u8> {
+    (0..current.len())
+        .map(|i| {
+            let left = if i >= distance {
+                current[i - distance]
+            } else {
+                0
+            };
+            let up = previous.map_or(0, |previous| previous[i]);
+            let upper_left = match previous {
+                Some(previous) if i >= distance => previous[i - distance],
+                _ => 0,
+            };
+            current[i].wrapping_sub(match filter_type {
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, upper_left),
+                _ => 0,
+            })
+        })
+        .collect()
+}
+
+fn paeth(left: u8, up: u8, upper_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - upper_left as i16;
+    let distance_left = (estimate - left as i16).abs();
+    let distance_up = (estimate - up as i16).abs();
+    let distance_upper_left = (estimate - upper_left as i16).abs();
+    if distance_left <= distance_up && distance_left <= distance_upper_left {
+        left
+    } else if distance_up <= distance_upper_left {
+        up
+    } else {
+        upper_left
+    }
+}
+
+/// Samples of a decoded image, row by row, one per channel of every pixel.
+/// Values are as stored: bit depths below 8 are not scaled up, and indexed
+/// images hold palette indices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Samples {
+    /// Bit depths 1, 2, 4 and 8.
+    Eight(Vec<u8>),
+    /// Bit depth 16.
+    Sixteen(Vec<u16>),
+}
+
+impl Samples {
+    pub fn len(&self) -> usize {
+        match self {
+            Samples::Eight(samples) => samples.len(),
+            Samples::Sixteen(samples) => samples.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<u16> {
+        match self {
+            Samples::Eight(samples) => samples.get(index).map(|&sample| sample as u16),
+            Samples::Sixteen(samples) => samples.get(index).copied(),
+        }
+    }
+}
+
+/// The pixels of a PNG image, as returned by [`decode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    ihdr: Ihdr,
+    palette: Option<Vec<[u8; 3]>>,
+    samples: Samples,
+}
+
+impl Image {
+    pub fn ihdr(&self) -> &Ihdr {
+        &self.ihdr
+    }
+
+    pub fn width(&self) -> usize {
+        self.ihdr.width() as usize
+    }
+
+    pub fn height(&self) -> usize {
+        self.ihdr.height() as usize
+    }
+
+    pub fn channels(&self) -> usize {
+        self.ihdr.color_type().channels() as usize
+    }
+
+    /// The `PLTE` entries of an indexed-color image.
+    pub fn palette(&self) -> Option<&[[u8; 3]]> {
+        self.palette.as_deref()
+    }
+
+    pub fn samples(&self) -> &Samples {
+        &self.samples
+    }
+
+    /// The sample of `channel` of the pixel in column `x` and row `y`.
+    pub fn sample(&self, x: usize, y: usize, channel: usize) -> Option<u16> {
+        if x >= self.width() || y >= self.height() || channel >= self.channels() {
+            return None;
+        }
+        self.samples
+            .get((y * self.width() + x) * self.channels() + channel)
+    }
+
+    /// Every pixel as 8-bit red, green, blue and alpha, with palette indices
+    /// looked up and other bit depths scaled. Transparency from `tRNS` is not
+    /// applied.
+    pub fn to_rgba8(&self) -> Result<Vec<[u8; 4]>> {
+        let depth = self.ihdr.bit_depth();
+        let max = (1u32 << depth) - 1;
+        let scale = |sample: u16| (sample as u32 * 255 / max) as u8;
+        let mut pixels = Vec::with_capacity(self.width() * self.height());
+        for index in (0..self.samples.len()).step_by(self.channels()) {
+            let sample = |channel: usize| self.samples.get(index + channel).unwrap_or(0);
+            pixels.push(match self.ihdr.color_type() {
+                ColorType::Grayscale => {
+                    let gray = scale(sample(0));
+                    [gray, gray, gray, 255]
+                }
+                ColorType::GrayscaleAlpha => {
+                    let gray = scale(sample(0));
+                    [gray, gray, gray, scale(sample(1))]
+                }
+                ColorType::Truecolor => [scale(sample(0)), scale(sample(1)), scale(sample(2)), 255],
+                ColorType::TruecolorAlpha => [
+                    scale(sample(0)),
+                    scale(sample(1)),
+                    scale(sample(2)),
+                    scale(sample(3)),
+                ],
+                ColorType::Indexed => {
+                    let [red, green, blue] = self
+                        .palette()
+                        .and_then(|palette| palette.get(sample(0) as usize))
+                        .ok_or(Error::InvalidImageData("Palette index out of range."))?;
+                    [*red, *green, *blue, 255]
+                }
+            });
+        }
+        Ok(pixels)
+    }
+}
+
+/// Decodes the pixels of `png`: inflates the concatenated `IDAT` data,
+/// reverses the scanline filters, unpacks the samples of every bit depth and
+/// puts the pixels of interlaced images back in place.
+pub fn decode(png: &Png) -> Result<Image> {
+    let ihdr = png.ihdr()?;
+    let palette = match png.chunk_by_type("PLTE") {
+        Some(chunk) => Some(parse_palette(chunk.data())?),
+        None if ihdr.color_type() == ColorType::Indexed => {
+            return Err(Error::InvalidImageData("Indexed image without a palette."))
+        }
+        None => None,
+    };
+
+    let scanlines = Scanlines::decode(png, &ihdr)?;
+    let (width, channels) = (ihdr.width() as usize, ihdr.color_type().channels() as usize);
+    let depth = ihdr.bit_depth() as usize;
+    let length = ihdr.samples();
+    let samples_per_row = width * channels;
+
+    // Index in the image of every sample stored in the data, in storage order.
+    let mut start = 0;
+    let mut targets = Vec::with_capacity(length);
+    let mut lines = Vec::with_capacity(length);
+    for pass in passes(&ihdr) {
+        for row in 0..pass.height {
+            let y = pass.y + row * pass.dy;
+            for column in 0..pass.width {
+                let x = pass.x + column * pass.dx;
+                for channel in 0..channels {
+                    targets.push(y * samples_per_row + x * channels + channel);
+                    lines.push((start, (column * channels + channel) * depth));
+                }
+            }
+            start += pass.stride;
+        }
+    }
+
+    let data = &scanlines.data;
+    let samples = if depth == 16 {
+        let mut samples = vec![0; length];
+        for (&target, &(line, bit)) in targets.iter().zip(&lines) {
+            let byte = line + bit / 8;
+            samples[target] = u16::from_be_bytes([data[byte], data[byte + 1]]);
+        }
+        Samples::Sixteen(samples)
+    } else {
+        let mask = ((1u16 << depth) - 1) as u8;
+        let mut samples = vec![0; length];
+        for (&target, &(line, bit)) in targets.iter().zip(&lines) {
+            let shift = 8 - depth - bit % 8;
+            samples[target] = (data[line + bit / 8] >> shift) & mask;
+        }
+        Samples::Eight(samples)
+    };
+
+    Ok(Image {
+        ihdr,
+        palette,
+        samples,
+    })
+}
+
+fn parse_palette(data: &[u8]) -> Result<Vec<[u8; 3]>> {
+    if data.is_empty() || !data.len().is_multiple_of(3) || data.len() > 256 * 3 {
+        return Err(Error::InvalidImageData("Invalid palette."));
+    }
+    Ok(data
+        .chunks_exact(3)
+        .map(|entry| [entry[0], entry[1], entry[2]])
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::Chunk, chunk_type::ChunkType};
+    use std::str::FromStr;
+
+    const PNG_FILE: &[u8] = include_bytes!("../image/dice.png");
+
+    /// Builds a PNG holding `samples`, stored with filter type 0 for every
+    /// scanline, and interlaced with Adam7 if asked.
+    fn build_png(
+        width: u32,
+        height: u32,
+        depth: u8,
+        color_type: ColorType,
+        interlaced: bool,
+        samples: &[u16],
+        palette: Option<&[u8]>,
+    ) -> Png {
+        let interlace = if interlaced { 1 } else { 0 };
+        let mut header = Vec::new();
+        header.extend(width.to_be_bytes());
+        header.extend(height.to_be_bytes());
+        header.extend([depth, color_type as u8, 0, 0, interlace]);
+        let ihdr = Ihdr::try_from(&header[..]).unwrap();
+
+        let channels = color_type.channels() as usize;
+        let mut raw = Vec::new();
+        for pass in passes(&ihdr) {
+            for row in 0..pass.height {
+                raw.push(0);
+                let mut bits = Vec::new();
+                for column in 0..pass.width {
+                    let (x, y) = (pass.x + column * pass.dx, pass.y + row * pass.dy);
+                    for channel in 0..channels {
+                        let sample = samples[(y * width as usize + x) * channels + channel];
+                        bits.extend((0..depth).rev().map(|shift| (sample >> shift) & 1));
+                    }
+                }
+                bits.resize(pass.stride * 8, 0);
+                raw.extend(
+                    bits.chunks(8)
+                        .map(|byte| byte.iter().fold(0, |acc, &bit| (acc << 1) | bit as u8)),
+                );
+            }
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&raw).unwrap();
+
+        let mut chunks = vec![ihdr.as_chunk()];
+        if let Some(palette) = palette {
+            chunks.push(Chunk::new(
+                ChunkType::from_str("PLTE").unwrap(),
+                palette.to_vec(),
+            ));
+        }
+        chunks.push(Chunk::new(
+            ChunkType::from_str("IDAT").unwrap(),
+            encoder.finish().unwrap(),
+        ));
+        chunks.push(Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()));
+        Png::from_chunks(chunks)
+    }
+
+    fn pattern(length: usize, depth: u8) -> Vec<u16> {
+        let max = ((1u32 << depth) - 1) as usize;
+        (0..length)
+            .map(|i| ((i * 7 + i / 5) % (max + 1)) as u16)
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_reference_image() {
+        let png = Png::try_from(PNG_FILE).unwrap();
+        let image = decode(&png).unwrap();
+        assert_eq!(
+            (image.width(), image.height(), image.channels()),
+            (671, 448, 3)
+        );
+        assert_eq!(image.samples().len(), 671 * 448 * 3);
+        // Reference values from an independent decoder.
+        assert_eq!(image.sample(100, 100, 0), Some(255));
+        assert_eq!(image.sample(100, 100, 1), Some(102));
+        assert_eq!(image.sample(100, 100, 2), Some(102));
+        assert_eq!(image.sample(670, 447, 2), Some(255));
+        assert_eq!(image.sample(671, 0, 0), None);
+        let Samples::Eight(samples) = image.samples() else {
+            unreachable!()
+        };
+        let sum: u64 = samples.iter().map(|&sample| sample as u64).sum();
+        assert_eq!(sum, 207_368_053);
+    }
+
+    #[test]
+    fn test_all_depths_and_color_types() {
+        for color_type in [
+            ColorType::Grayscale,
+            ColorType::Truecolor,
+            ColorType::GrayscaleAlpha,
+            ColorType::TruecolorAlpha,
+        ] {
+            for &depth in color_type.allowed_bit_depths() {
+                for interlaced in [false, true] {
+                    let (width, height) = (13, 11);
+                    let length = width * height * color_type.channels() as usize;
+                    let samples = pattern(length, depth);
+                    let png = build_png(
+                        width as u32,
+                        height as u32,
+                        depth,
+                        color_type,
+                        interlaced,
+                        &samples,
+                        None,
+                    );
+                    let image = decode(&png).unwrap();
+                    let decoded: Vec<u16> = (0..length)
+                        .map(|i| image.samples().get(i).unwrap())
+                        .collect();
+                    assert_eq!(
+                        decoded, samples,
+                        "{:?} {} {}",
+                        color_type, depth, interlaced
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_indexed_image() {
+        let palette: Vec<u8> = (0..16).flat_map(|i| [i * 16, 255 - i * 16, i]).collect();
+        for interlaced in [false, true] {
+            let samples = pattern(9 * 5, 4);
+            let png = build_png(
+                9,
+                5,
+                4,
+                ColorType::Indexed,
+                interlaced,
+                &samples,
+                Some(&palette),
+            );
+            let image = decode(&png).unwrap();
+            assert_eq!(image.palette().unwrap().len(), 16);
+            let rgba = image.to_rgba8().unwrap();
+            let index = samples[10] as u8;
+            assert_eq!(rgba[10], [index * 16, 255 - index * 16, index, 255]);
+        }
+
+        let png = build_png(9, 5, 4, ColorType::Indexed, false, &pattern(45, 4), None);
+        assert!(decode(&png).is_err());
+        let png = build_png(9, 5, 4, ColorType::Indexed, false, &[15; 45], Some(&[0; 6]));
+        assert!(decode(&png).unwrap().to_rgba8().is_err());
+    }
+
+    #[test]
+    fn test_to_rgba8_scales() {
+        let png = build_png(2, 1, 2, ColorType::Grayscale, false, &[1, 3], None);
+        let rgba = decode(&png).unwrap().to_rgba8().unwrap();
+        assert_eq!(rgba, vec![[85, 85, 85, 255], [255, 255, 255, 255]]);
+
+        let png = build_png(
+            1,
+            1,
+            16,
+            ColorType::GrayscaleAlpha,
+            false,
+            &[65535, 0],
+            None,
+        );
+        let rgba = decode(&png).unwrap().to_rgba8().unwrap();
+        assert_eq!(rgba, vec![[255, 255, 255, 0]]);
+    }
+
+    #[test]
+    fn test_tiny_interlaced_image() {
+        // A 1x1 image only has data in the first Adam7 pass.
+        let png = build_png(1, 1, 8, ColorType::Grayscale, true, &[42], None);
+        assert_eq!(passes(png.ihdr().as_ref().unwrap()).len(), 1);
+        assert_eq!(decode(&png).unwrap().sample(0, 0, 0), Some(42));
+    }
+
+    #[test]
+    fn test_scanlines_round_trip() {
+        let png = Png::try_from(PNG_FILE).unwrap();
+        let ihdr = png.ihdr().unwrap();
+        let scanlines = Scanlines::decode(&png, &ihdr).unwrap();
+        let mut copy = Png::try_from(PNG_FILE).unwrap();
+        copy.replace_image_data(&scanlines.encode(&ihdr).unwrap());
+        assert_eq!(decode(&copy).unwrap(), decode(&png).unwrap());
+    }
+
+    #[test]
+    fn test_filter_roundtrip() {
+        let previous = [10, 20, 30, 40, 50, 60];
+        let current = [200, 3, 17, 255, 0, 128];
+        for filter_type in 0..5 {
+            let mut unfiltered = filter(filter_type, 3, &current, Some(&previous));
+            unfilter(filter_type, 3, &mut unfiltered, Some(&previous)).unwrap();
+            assert_eq!(unfiltered, current);
+        }
+        assert!(unfilter(5, 3, &mut [0; 6], None).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_image_data() {
+        let mut png = Png::try_from(PNG_FILE).unwrap();
+        png.replace_image_data(b"not zlib");
+        assert!(matches!(decode(&png), Err(Error::InvalidImageData(_))));
+    }
+}
@@ -0,0 +1,228 @@
+use crate::{png::Png, Error, Result};
+use flate2::{Decompress, FlushDecompress, Status};
+
+/// Marks a payload appended to the image data by [`embed`].
+pub const MAGIC: [u8; 4] = *b"StDf";
+
+/// Trailer layout: magic (4) | payload length (4). The payload follows.
+pub const HEADER_LENGTH: usize = MAGIC.len() + 4;
+
+/// How much the output buffer grows by while inflating.
+const INFLATE_STEP: usize = 1 << 16;
+
+/// Inflates the zlib stream at the start of `data`, returning the image bytes
+/// and the length of the stream, up to and including its Adler-32 checksum.
+/// Streams that are cut short, fail the checksum or inflate to more than an
+/// image of this size can hold are rejected.
+fn inflate(png: &Png, data: &[u8]) -> Result<(Vec<u8>, usize)> {
+    let ihdr = png.ihdr()?;
+    // Every scanline of every Adam7 pass has a filter byte and rounds up to a
+    // whole byte, and there are fewer than twice as many pass scanlines as
+    // image rows, so the image data is never larger than this.
+    let limit = (ihdr.stride() + 2)
+        .checked_mul(2 * ihdr.height() as usize)
+        .ok_or(Error::Unsupported("Image is too large."))?;
+
+    let mut decompress = Decompress::new(true);
+    let mut inflated = Vec::new();
+    loop {
+        if inflated.len() > limit {
+            return Err(Error::InvalidImageData(
+                "Image data inflates to more than the image holds.",
+            ));
+        }
+        if inflated.len() == inflated.capacity() {
+            inflated.reserve(INFLATE_STEP);
+        }
+        let (total_in, total_out) = (decompress.total_in(), decompress.total_out());
+        let status = decompress
+            .decompress_vec(
+                &data[total_in as usize..],
+                &mut inflated,
+                FlushDecompress::None,
+            )
+            .map_err(|_| Error::InvalidImageData("Corrupt zlib stream."))?;
+        match status {
+            Status::StreamEnd => break,
+            _ if decompress.total_in() == total_in && decompress.total_out() == total_out => {
+                return Err(Error::InvalidImageData("Truncated zlib stream."));
+            }
+            _ => {}
+        }
+    }
+    if inflated.len() > limit {
+        return Err(Error::InvalidImageData(
+            "Image data inflates to more than the image holds.",
+        ));
+    }
+    Ok((inflated, decompress.total_in() as usize))
+}
+
+/// Number of payload bytes that [`embed`] can hide in the image, or `None`
+/// if there is no limit. The image data only has to be a valid zlib stream;
+/// the payload itself is only limited by its 4 byte length, which no real
+/// message comes close to.
+pub fn capacity(png: &Png) -> Result<Option<usize>> {
+    inflate(png, &png.image_data())?;
+    Ok(None)
+}
+
+/// Number of bytes in the `IDAT` chunks after the end of the zlib stream.
+/// Encoders never write any, whether it is a payload from [`embed`] or not.
+pub fn trailing_length(png: &Png) -> Result<usize> {
+    let data = png.image_data();
+    let (_, end) = inflate(png, &data)?;
+    Ok(data.len() - end)
+}
+
+/// Hides `payload` after the end of the zlib stream in the `IDAT` chunks.
+/// Decoders stop reading at the Adler-32 checksum, so the pixels are left
+/// untouched. Any payload hidden before is replaced.
+///
+/// The result is checked by inflating the image data again and extracting
+/// the payload; if either differs, the image is left as it was.
+pub fn embed(png: &mut Png, payload: &[u8]) -> Result<()> {
+    let length = u32::try_from(payload.len()).map_err(|_| Error::MessageTooLarge {
+        size: payload.len(),
+        capacity: u32::MAX as usize,
+    })?;
+    let original = png.image_data();
+    let (pixels, end) = inflate(png, &original)?;
+
+    let mut data = Vec::with_capacity(end + HEADER_LENGTH + payload.len());
+    data.extend_from_slice(&original[..end]);
+    data.extend_from_slice(&MAGIC);
+    data.extend(length.to_be_bytes());
+    data.extend_from_slice(payload);
+    png.replace_image_data(&data);
+
+    let verified = inflate(png, &png.image_data()).is_ok_and(|(inflated, _)| inflated == pixels)
+        && extract(png).is_ok_and(|extracted| extracted == payload);
+    if !verified {
+        png.replace_image_data(&original);
+        return Err(Error::VerificationFailed);
+    }
+    Ok(())
+}
+
+/// Recovers a payload hidden with [`embed`].
+pub fn extract(png: &Png) -> Result<Vec<u8>> {
+    let data = png.image_data();
+    let (_, end) = inflate(png, &data)?;
+    let trailer = &data[end..];
+    if trailer.len() < HEADER_LENGTH || !trailer.starts_with(&MAGIC) {
+        return Err(Error::MessageNotFound);
+    }
+    let length = u32::from_be_bytes(trailer[MAGIC.len()..HEADER_LENGTH].try_into().unwrap());
+    trailer[HEADER_LENGTH..]
+        .get(..length as usize)
+        .map(<[u8]>::to_vec)
+        .ok_or(Error::MessageNotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    fn testing_png() -> Png {
+        Png::try_from(&include_bytes!("../image/dice.png")[..]).unwrap()
+    }
+
+    #[test]
+    fn test_embed_extract() {
+        let mut png = testing_png();
+        embed(&mut png, b"hidden in the slack").unwrap();
+        let png = Png::try_from(&png.as_bytes()[..]).unwrap();
+        assert_eq!(extract(&png).unwrap(), b"hidden in the slack");
+    }
+
+    #[test]
+    fn test_pixels_are_unchanged() {
+        let original = testing_png();
+        let mut png = testing_png();
+        embed(&mut png, &[0xab; 10_000]).unwrap();
+
+        let data = original.image_data();
+        assert_eq!(png.image_data()[..data.len()], data[..]);
+        assert_eq!(
+            inflate(&png, &png.image_data()).unwrap().0,
+            inflate(&original, &data).unwrap().0
+        );
+        assert_eq!(
+            png.chunks().len(),
+            original.chunks().len() + 1,
+            "the payload spills into one more IDAT chunk"
+        );
+    }
+
+    #[test]
+    fn test_embed_replaces_previous_payload() {
+        let mut png = testing_png();
+        embed(&mut png, b"first message, which is longer").unwrap();
+        embed(&mut png, b"second").unwrap();
+        assert_eq!(extract(&png).unwrap(), b"second");
+    }
+
+    #[test]
+    fn test_capacity() {
+        assert_eq!(capacity(&testing_png()).unwrap(), None);
+
+        let mut png = testing_png();
+        png.replace_image_data(b"not zlib");
+        assert!(capacity(&png).is_err());
+    }
+
+    #[test]
+    fn test_incomplete_stream() {
+        let mut png = testing_png();
+        let data = png.image_data();
+        png.replace_image_data(&data[..data.len() - 1]);
+        assert!(capacity(&png).is_err());
+
+        let mut data = testing_png().image_data();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        png.replace_image_data(&data);
+        assert!(capacity(&png).is_err());
+    }
+
+    #[test]
+    fn test_stream_larger_than_image() {
+        let mut png = testing_png();
+        let pixels = inflate(&png, &png.image_data()).unwrap().0;
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        // The limit allows for interlacing, so twice the pixels still fit.
+        for _ in 0..3 {
+            encoder.write_all(&pixels).unwrap();
+        }
+        png.replace_image_data(&encoder.finish().unwrap());
+        assert!(capacity(&png).is_err());
+    }
+
+    #[test]
+    fn test_trailing_length() {
+        let mut png = testing_png();
+        assert_eq!(trailing_length(&png).unwrap(), 0);
+        embed(&mut png, b"hidden").unwrap();
+        assert_eq!(trailing_length(&png).unwrap(), HEADER_LENGTH + 6);
+    }
+
+    #[test]
+    fn test_extract_without_message() {
+        assert!(matches!(
+            extract(&testing_png()),
+            Err(Error::MessageNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_truncated_payload() {
+        let mut png = testing_png();
+        embed(&mut png, b"hidden").unwrap();
+        let data = png.image_data();
+        png.replace_image_data(&data[..data.len() - 1]);
+        assert!(matches!(extract(&png), Err(Error::MessageNotFound)));
+    }
+}
@@ -0,0 +1,348 @@
+use crate::{
+    chunk::Chunk,
+    decoder::{self, Image, Samples},
+    ihdr::{ColorType, Ihdr},
+    png::Png,
+    Error, Result,
+};
+use flate2::{write::ZlibEncoder, Compression};
+use std::io::Write;
+
+/// How the filter type of each scanline is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterStrategy {
+    /// The same filter type, 0 to 4, for every scanline.
+    Fixed(u8),
+    /// For every scanline, the filter type whose output has the smallest sum
+    /// of absolute values when read as signed bytes. Indexed-color images and
+    /// bit depths below 8 always use filter type 0, which compresses them
+    /// best.
+    Adaptive,
+}
+
+/// Settings for [`encode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodeOptions {
+    pub filter: FilterStrategy,
+    /// Deflate compression level, from 0 (store only) to 9 (smallest).
+    pub level: u32,
+    /// Largest number of bytes in one `IDAT` chunk.
+    pub chunk_size: usize,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> EncodeOptions {
+        EncodeOptions {
+            filter: FilterStrategy::Adaptive,
+            level: 6,
+            chunk_size: Png::IDAT_CHUNK_SIZE,
+        }
+    }
+}
+
+impl EncodeOptions {
+    fn check(&self) -> Result<()> {
+        if let FilterStrategy::Fixed(5..) = self.filter {
+            return Err(Error::Unsupported("Filter types go from 0 to 4."));
+        }
+        if self.level > 9 {
+            return Err(Error::Unsupported(
+                "Compression level must be between 0 and 9.",
+            ));
+        }
+        if self.chunk_size == 0 || self.chunk_size > Chunk::MAX_LENGTH as usize {
+            return Err(Error::Unsupported(
+                "IDAT chunk size must be between 1 and 2^31-1 bytes.",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Compresses `samples`, laid out as [`decoder::decode`] returns them, into
+/// the zlib stream stored in the `IDAT` chunks of an image with header `ihdr`.
+pub fn encode(ihdr: &Ihdr, samples: &Samples, options: &EncodeOptions) -> Result<Vec<u8>> {
+    options.check()?;
+    check_samples(ihdr, samples)?;
+
+    let adaptive = options.filter == FilterStrategy::Adaptive
+        && ihdr.color_type() != ColorType::Indexed
+        && ihdr.bit_depth() >= 8;
+    let distance = ihdr.bytes_per_pixel();
+    let depth = ihdr.bit_depth() as usize;
+    let channels = ihdr.color_type().channels() as usize;
+    let samples_per_row = ihdr.width() as usize * channels;
+
+    let mut filtered = Vec::new();
+    for pass in decoder::passes(ihdr) {
+        let mut previous: Option<Vec<u8>> = None;
+        for row in 0..pass.height {
+            let y = pass.y + row * pass.dy;
+            let mut line = Vec::with_capacity(pass.stride);
+            let mut bits = 0;
+            let mut filled = 0;
+            for column in 0..pass.width {
+                let x = pass.x + column * pass.dx;
+                for channel in 0..channels {
+                    let index = y * samples_per_row + x * channels + channel;
+                    let sample = samples.get(index).unwrap_or(0);
+                    match depth {
+                        16 => line.extend(sample.to_be_bytes()),
+                        8 => line.push(sample as u8),
+                        _ => {
+                            bits = (bits << depth) | sample as u8;
+                            filled += depth;
+                            if filled == 8 {
+                                line.push(bits);
+                                (bits, filled) = (0, 0);
+                            }
+                        }
+                    }
+                }
+            }
+            if filled > 0 {
+                line.push(bits << (8 - filled));
+            }
+
+            let (filter_type, output) = if adaptive {
+                (0..5)
+                    .map(|filter_type| {
+                        let output =
+                            decoder::filter(filter_type, distance, &line, previous.as_deref());
+                        (filter_type, output)
+                    })
+                    .min_by_key(|(_, output)| {
+                        output
+                            .iter()
+                            .map(|&byte| (byte as i8).unsigned_abs() as u64)
+                            .sum::<u64>()
+                    })
+                    .unwrap()
+            } else {
+                let filter_type = match options.filter {
+                    FilterStrategy::Fixed(filter_type) => filter_type,
+                    FilterStrategy::Adaptive => 0,
+                };
+                let output = decoder::filter(filter_type, distance, &line, previous.as_deref());
+                (filter_type, output)
+            };
+            filtered.push(filter_type);
+            filtered.extend(output);
+            previous = Some(line);
+        }
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(options.level));
+    encoder.write_all(&filtered)?;
+    Ok(encoder.finish()?)
+}
+
+/// Replaces the pixels of `png` with those of `image`, which must have the
+/// same header. Every other chunk keeps its place.
+pub fn write_pixels(png: &mut Png, image: &Image, options: &EncodeOptions) -> Result<()> {
+    if png.ihdr()? != *image.ihdr() {
+        return Err(Error::InvalidImageData(
+            "Pixels do not match the image header.",
+        ));
+    }
+    let data = encode(image.ihdr(), image.samples(), options)?;
+    png.replace_image_data_split(&data, options.chunk_size);
+    Ok(())
+}
+
+fn check_samples(ihdr: &Ihdr, samples: &Samples) -> Result<()> {
+    let depth = ihdr.bit_depth();
+    let fits = match samples {
+        Samples::Eight(samples) if depth <= 8 => {
+            samples.iter().all(|&sample| (sample as u16) < 1 << depth)
+        }
+        Samples::Sixteen(_) => depth == 16,
+        Samples::Eight(_) => false,
+    };
+    if !fits || samples.len() != ihdr.samples() {
+        return Err(Error::InvalidImageData(
+            "Samples do not match the image header.",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::decode;
+
+    const PNG_FILE: &[u8] = include_bytes!("../image/dice.png");
+
+    fn testing_png() -> Png {
+        Png::try_from(PNG_FILE).unwrap()
+    }
+
+    fn header(depth: u8, color_type: ColorType, interlaced: bool) -> Ihdr {
+        let mut bytes = Vec::new();
+        bytes.extend(13u32.to_be_bytes());
+        bytes.extend(7u32.to_be_bytes());
+        bytes.extend([depth, color_type as u8, 0, 0, interlaced as u8]);
+        Ihdr::try_from(&bytes[..]).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_every_filter() {
+        let image = decode(&testing_png()).unwrap();
+        for filter in [
+            FilterStrategy::Fixed(0),
+            FilterStrategy::Fixed(1),
+            FilterStrategy::Fixed(2),
+            FilterStrategy::Fixed(3),
+            FilterStrategy::Fixed(4),
+            FilterStrategy::Adaptive,
+        ] {
+            let mut png = testing_png();
+            let options = EncodeOptions {
+                filter,
+                ..EncodeOptions::default()
+            };
+            write_pixels(&mut png, &image, &options).unwrap();
+            assert_eq!(decode(&png).unwrap(), image, "{:?}", filter);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_depths_and_interlacing() {
+        for (depth, color_type) in [
+            (1, ColorType::Grayscale),
+            (2, ColorType::Indexed),
+            (4, ColorType::Grayscale),
+            (8, ColorType::TruecolorAlpha),
+            (16, ColorType::GrayscaleAlpha),
+        ] {
+            for interlaced in [false, true] {
+                let ihdr = header(depth, color_type, interlaced);
+                let max = (1u32 << depth) - 1;
+                let values = (0..ihdr.samples() as u32).map(|i| (i * 37 + i / 3) & max);
+                let samples = if depth == 16 {
+                    Samples::Sixteen(values.map(|value| value as u16).collect())
+                } else {
+                    Samples::Eight(values.map(|value| value as u8).collect())
+                };
+
+                let mut chunks = vec![ihdr.as_chunk()];
+                if color_type == ColorType::Indexed {
+                    chunks.push(Chunk::new("PLTE".parse().unwrap(), vec![0; 12]));
+                }
+                chunks.push(Chunk::new("IEND".parse().unwrap(), Vec::new()));
+                let mut png = Png::from_chunks(chunks);
+                let data = encode(&ihdr, &samples, &EncodeOptions::default()).unwrap();
+                png.replace_image_data(&data);
+                assert_eq!(decode(&png).unwrap().samples(), &samples);
+            }
+        }
+    }
+
+    #[test]
+    fn test_adaptive_is_smaller_than_none() {
+        // Smooth gradients are what the prediction filters are for.
+        let mut bytes = Vec::new();
+        bytes.extend(64u32.to_be_bytes());
+        bytes.extend(64u32.to_be_bytes());
+        bytes.extend([8, ColorType::Truecolor as u8, 0, 0, 0]);
+        let ihdr = Ihdr::try_from(&bytes[..]).unwrap();
+        let samples = Samples::Eight(
+            (0..64 * 64 * 3)
+                .map(|i| ((i / 3 % 64) * 3 + (i / 192) * 2 + i % 3 * 17) as u8)
+                .collect(),
+        );
+        let size = |filter| {
+            let options = EncodeOptions {
+                filter,
+                ..EncodeOptions::default()
+            };
+            encode(&ihdr, &samples, &options).unwrap().len()
+        };
+        assert!(size(FilterStrategy::Adaptive) < size(FilterStrategy::Fixed(0)));
+    }
+
+    #[test]
+    fn test_level() {
+        let image = decode(&testing_png()).unwrap();
+        let size = |level| {
+            let options = EncodeOptions {
+                level,
+                ..EncodeOptions::default()
+            };
+            encode(image.ihdr(), image.samples(), &options)
+                .unwrap()
+                .len()
+        };
+        assert!(size(9) < size(0));
+    }
+
+    #[test]
+    fn test_chunk_size_keeps_ancillary_chunks() {
+        let mut png = testing_png();
+        let before: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .filter(|chunk_type| chunk_type != "IDAT")
+            .collect();
+        let image = decode(&png).unwrap();
+        let options = EncodeOptions {
+            chunk_size: 1000,
+            ..EncodeOptions::default()
+        };
+        write_pixels(&mut png, &image, &options).unwrap();
+
+        let chunk_types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        let after: Vec<&String> = chunk_types.iter().filter(|t| *t != "IDAT").collect();
+        assert_eq!(after, before.iter().collect::<Vec<_>>());
+        let first = chunk_types.iter().position(|t| t == "IDAT").unwrap();
+        let count = png.chunks_by_type("IDAT").count();
+        assert!(png
+            .chunks_by_type("IDAT")
+            .all(|chunk| chunk.length() <= 1000));
+        assert!(chunk_types[first..first + count]
+            .iter()
+            .all(|t| t == "IDAT"));
+        assert_eq!(decode(&png).unwrap(), image);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let image = decode(&testing_png()).unwrap();
+        for options in [
+            EncodeOptions {
+                filter: FilterStrategy::Fixed(5),
+                ..EncodeOptions::default()
+            },
+            EncodeOptions {
+                level: 10,
+                ..EncodeOptions::default()
+            },
+            EncodeOptions {
+                chunk_size: 0,
+                ..EncodeOptions::default()
+            },
+        ] {
+            assert!(encode(image.ihdr(), image.samples(), &options).is_err());
+        }
+    }
+
+    #[test]
+    fn test_samples_must_match_header() {
+        let ihdr = header(4, ColorType::Grayscale, false);
+        let options = EncodeOptions::default();
+        assert!(encode(&ihdr, &Samples::Eight(vec![0; 90]), &options).is_err());
+        assert!(encode(&ihdr, &Samples::Eight(vec![16; 91]), &options).is_err());
+        assert!(encode(&ihdr, &Samples::Sixteen(vec![0; 91]), &options).is_err());
+        assert!(encode(&ihdr, &Samples::Eight(vec![15; 91]), &options).is_ok());
+
+        let image = decode(&testing_png()).unwrap();
+        let mut png = Png::from_chunks(vec![ihdr.as_chunk()]);
+        assert!(write_pixels(&mut png, &image, &options).is_err());
+    }
+}
@@ -0,0 +1,363 @@
+use crate::{chunk_type::ChunkType, compression::Compression, validate::Violation};
+use std::{fmt, io, string::FromUtf8Error};
+
+/// Everything that can go wrong while reading, modifying or writing a PNG file.
+///
+/// Offsets are byte positions in the file, counted from the start of the
+/// signature, of the chunk the error refers to.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    InvalidSignature,
+    TruncatedChunk {
+        offset: u64,
+    },
+    /// Declared chunk length above the 2^31-1 limit set by the specification.
+    ChunkTooLong {
+        length: u32,
+        offset: u64,
+    },
+    /// `expected` is computed from the chunk, `actual` is stored in the file.
+    CrcMismatch {
+        chunk_type: ChunkType,
+        expected: u32,
+        actual: u32,
+        offset: u64,
+    },
+    InvalidChunkType(String),
+    /// Refused to hide a message in a critical chunk without `--force`.
+    CriticalChunkType(ChunkType),
+    InvalidIhdr(&'static str),
+    InvalidImageData(&'static str),
+    InvalidText(&'static str),
+    InvalidStructure(Vec<Violation>),
+    ChunkNotFound(String),
+    MessageNotFound,
+    MessageTooLarge {
+        size: usize,
+        capacity: usize,
+    },
+    Unsupported(&'static str),
+    InvalidUtf8(FromUtf8Error),
+    InvalidHeader(&'static str),
+    /// `missing` holds the first few missing indices only.
+    MissingFragments {
+        total: u32,
+        received: u32,
+        missing: Vec<u32>,
+    },
+    DuplicateFragment {
+        index: u32,
+    },
+    CorruptCompression,
+    CompressionUnavailable(Compression),
+    SecretRequired,
+    NotEncrypted,
+    DecryptionFailed,
+    InvalidPayload(&'static str),
+    KeyDerivation,
+    VerificationFailed,
+}
+
+impl Error {
+    /// Process exit code for the error. Codes are grouped by area: 10 for I/O,
+    /// 20s for malformed files, 30s for message handling, 40s for encryption
+    /// and 50 for failed self-checks.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Io(_) => 10,
+            Error::InvalidSignature => 20,
+            Error::TruncatedChunk { .. } => 21,
+            Error::CrcMismatch { .. } => 22,
+            Error::InvalidChunkType(_) => 23,
+            Error::InvalidIhdr(_) => 24,
+            Error::InvalidImageData(_) => 25,
+            Error::InvalidStructure(_) => 26,
+            Error::ChunkTooLong { .. } => 27,
+            Error::InvalidText(_) => 28,
+            Error::CriticalChunkType(_) => 29,
+            Error::ChunkNotFound(_) => 30,
+            Error::MessageNotFound => 31,
+            Error::MessageTooLarge { .. } => 32,
+            Error::Unsupported(_) => 33,
+            Error::InvalidUtf8(_) => 34,
+            Error::InvalidHeader(_) => 35,
+            Error::MissingFragments { .. } => 36,
+            Error::DuplicateFragment { .. } => 37,
+            Error::CorruptCompression => 38,
+            Error::CompressionUnavailable(_) => 39,
+            Error::SecretRequired => 40,
+            Error::NotEncrypted => 41,
+            Error::DecryptionFailed => 42,
+            Error::InvalidPayload(_) => 43,
+            Error::KeyDerivation => 44,
+            Error::VerificationFailed => 50,
+        }
+    }
+
+    /// Shifts the offset of positional errors by `base`, for errors raised
+    /// while parsing a slice that starts at `base` in the file.
+    pub fn offset_by(self, base: u64) -> Error {
+        match self {
+            Error::TruncatedChunk { offset } => Error::TruncatedChunk {
+                offset: offset + base,
+            },
+            Error::ChunkTooLong { length, offset } => Error::ChunkTooLong {
+                length,
+                offset: offset + base,
+            },
+            Error::CrcMismatch {
+                chunk_type,
+                expected,
+                actual,
+                offset,
+            } => Error::CrcMismatch {
+                chunk_type,
+                expected,
+                actual,
+                offset: offset + base,
+            },
+            error => error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::InvalidSignature => write!(f, "Invalid PNG file. Incorrect header."),
+            Error::TruncatedChunk { offset } => {
+                write!(
+                    f,
+                    "Invalid PNG file. Chunk at offset {} is truncated.",
+                    offset
+                )
+            }
+            Error::ChunkTooLong { length, offset } => write!(
+                f,
+                "Invalid PNG file. Chunk at offset {} declares {} bytes of data, more than the 2^31-1 allowed.",
+                offset, length
+            ),
+            Error::CrcMismatch {
+                chunk_type,
+                expected,
+                actual,
+                offset,
+            } => write!(
+                f,
+                "Invalid CRC for {} chunk at offset {}. Expected {:#010x}, found {:#010x}.",
+                chunk_type, offset, expected, actual
+            ),
+            Error::InvalidChunkType(chunk_type) => write!(
+                f,
+                "Invalid chunk type {:?}. Chunk types are four ASCII letters.",
+                chunk_type
+            ),
+            Error::CriticalChunkType(chunk_type) => write!(
+                f,
+                "Chunk type {} is critical, so the image may no longer display. Use --force to write it anyway.",
+                chunk_type
+            ),
+            Error::InvalidIhdr(reason) => write!(f, "Invalid IHDR chunk. {}", reason),
+            Error::InvalidImageData(reason) => write!(f, "Invalid image data. {}", reason),
+            Error::InvalidText(reason) => write!(f, "Invalid text chunk. {}", reason),
+            Error::InvalidStructure(violations) => {
+                write!(f, "Invalid PNG file.")?;
+                if let Some(violation) = violations.first() {
+                    write!(f, " {}", violation)?;
+                }
+                if violations.len() > 1 {
+                    write!(f, " ({} more)", violations.len() - 1)?;
+                }
+                Ok(())
+            }
+            Error::ChunkNotFound(chunk_type) => write!(f, "Chunk {} not found.", chunk_type),
+            Error::MessageNotFound => write!(f, "No message found in the image."),
+            Error::MessageTooLarge { size, capacity } => write!(
+                f,
+                "Message of {} bytes is too large. The image can hold {} bytes.",
+                size, capacity
+            ),
+            Error::Unsupported(reason) => write!(f, "Unsupported image. {}", reason),
+            Error::InvalidUtf8(_) => write!(
+                f,
+                "Message is not valid UTF-8. Use --output-file to save it as is."
+            ),
+            Error::InvalidHeader(reason) => write!(f, "Invalid payload header. {}", reason),
+            Error::MissingFragments {
+                total,
+                received,
+                missing,
+            } => {
+                write!(
+                    f,
+                    "Message is incomplete. Found {} of {} fragments, missing ",
+                    received, total
+                )?;
+                let missing: Vec<String> = missing.iter().map(u32::to_string).collect();
+                write!(f, "{}", missing.join(", "))?;
+                if (total - received) as usize > missing.len() {
+                    write!(f, ", ...")
+                } else {
+                    write!(f, ".")
+                }
+            }
+            Error::DuplicateFragment { index } => {
+                write!(f, "Message fragment {} appears more than once.", index)
+            }
+            Error::CorruptCompression => write!(f, "Compressed message is corrupt."),
+            Error::CompressionUnavailable(compression) => write!(
+                f,
+                "Compression with {0} is not available. Rebuild with --features {0}.",
+                compression
+            ),
+            Error::SecretRequired => {
+                write!(f, "Message is encrypted. Provide --password or --key-file.")
+            }
+            Error::NotEncrypted => write!(f, "Message is not encrypted."),
+            Error::DecryptionFailed => {
+                write!(f, "Decryption failed. Wrong password or corrupted payload.")
+            }
+            Error::InvalidPayload(reason) => write!(f, "Invalid encrypted payload. {}", reason),
+            Error::KeyDerivation => write!(f, "Key derivation failed."),
+            Error::VerificationFailed => write!(
+                f,
+                "Embedding failed verification. The image would not decode to the same pixels."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::InvalidUtf8(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(e: FromUtf8Error) -> Self {
+        Error::InvalidUtf8(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_offset_by() {
+        let error = Error::TruncatedChunk { offset: 4 }.offset_by(8);
+        assert!(matches!(error, Error::TruncatedChunk { offset: 12 }));
+
+        let error = Error::CrcMismatch {
+            chunk_type: ChunkType::from_str("RuSt").unwrap(),
+            expected: 1,
+            actual: 2,
+            offset: 0,
+        }
+        .offset_by(33);
+        assert!(matches!(error, Error::CrcMismatch { offset: 33, .. }));
+
+        let error = Error::MessageNotFound.offset_by(33);
+        assert!(matches!(error, Error::MessageNotFound));
+    }
+
+    #[test]
+    fn test_crc_mismatch_display() {
+        let error = Error::CrcMismatch {
+            chunk_type: ChunkType::from_str("RuSt").unwrap(),
+            expected: 0xabcd,
+            actual: 0x1234,
+            offset: 33,
+        };
+        assert_eq!(
+            error.to_string(),
+            "Invalid CRC for RuSt chunk at offset 33. Expected 0x0000abcd, found 0x00001234."
+        );
+    }
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let errors = [
+            Error::Io(io::Error::other("")),
+            Error::InvalidSignature,
+            Error::TruncatedChunk { offset: 0 },
+            Error::ChunkTooLong {
+                length: 0,
+                offset: 0,
+            },
+            Error::CrcMismatch {
+                chunk_type: ChunkType::from_str("RuSt").unwrap(),
+                expected: 0,
+                actual: 0,
+                offset: 0,
+            },
+            Error::InvalidChunkType(String::new()),
+            Error::CriticalChunkType(ChunkType::from_str("RUST").unwrap()),
+            Error::InvalidIhdr(""),
+            Error::InvalidImageData(""),
+            Error::InvalidText(""),
+            Error::InvalidStructure(Vec::new()),
+            Error::ChunkNotFound(String::new()),
+            Error::MessageNotFound,
+            Error::MessageTooLarge {
+                size: 0,
+                capacity: 0,
+            },
+            Error::Unsupported(""),
+            Error::InvalidUtf8(String::from_utf8(vec![255]).unwrap_err()),
+            Error::InvalidHeader(""),
+            Error::MissingFragments {
+                total: 0,
+                received: 0,
+                missing: Vec::new(),
+            },
+            Error::DuplicateFragment { index: 0 },
+            Error::CorruptCompression,
+            Error::CompressionUnavailable(Compression::Zstd),
+            Error::SecretRequired,
+            Error::NotEncrypted,
+            Error::DecryptionFailed,
+            Error::InvalidPayload(""),
+            Error::KeyDerivation,
+            Error::VerificationFailed,
+        ];
+        let mut codes: Vec<u8> = errors.iter().map(Error::exit_code).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+        assert!(!codes.contains(&0) && !codes.contains(&1) && !codes.contains(&2));
+    }
+
+    #[test]
+    fn test_missing_fragments_display() {
+        let error = Error::MissingFragments {
+            total: 5,
+            received: 3,
+            missing: vec![1, 4],
+        };
+        assert_eq!(
+            error.to_string(),
+            "Message is incomplete. Found 3 of 5 fragments, missing 1, 4."
+        );
+
+        let error = Error::MissingFragments {
+            total: 50,
+            received: 3,
+            missing: vec![1, 4],
+        };
+        assert!(error.to_string().ends_with("missing 1, 4, ..."));
+    }
+}
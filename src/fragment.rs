@@ -0,0 +1,271 @@
+use crate::{chunk::Chunk, Error, Result};
+use std::collections::{BTreeMap, HashMap};
+
+/// Marks chunk data holding one fragment of a payload split by [`split`].
+pub const MAGIC: [u8; 4] = *b"StFr";
+
+/// Header layout: magic (4) | payload id (4) | index (4) | total (4).
+/// The fragment data follows.
+pub const HEADER_LENGTH: usize = MAGIC.len() + 4 + 4 + 4;
+
+/// Payloads up to this many bytes are stored in a single chunk.
+pub const DEFAULT_SIZE: usize = 1 << 16;
+
+/// Largest number of missing fragment indices kept in
+/// [`Error::MissingFragments`].
+const MAX_REPORTED: usize = 10;
+
+struct Fragment<'a> {
+    id: u32,
+    index: u32,
+    total: u32,
+    data: &'a [u8],
+}
+
+impl<'a> Fragment<'a> {
+    fn parse(bytes: &'a [u8]) -> Option<Fragment<'a>> {
+        if bytes.len() < HEADER_LENGTH || !bytes.starts_with(&MAGIC) {
+            return None;
+        }
+        let field = |i: usize| {
+            let start = MAGIC.len() + 4 * i;
+            u32::from_be_bytes(bytes[start..start + 4].try_into().unwrap())
+        };
+        Some(Fragment {
+            id: field(0),
+            index: field(1),
+            total: field(2),
+            data: &bytes[HEADER_LENGTH..],
+        })
+    }
+}
+
+/// Splits `data` into the contents of one chunk per fragment of at most `size`
+/// bytes. Data that fits in one fragment is returned as is, without a header,
+/// so small payloads look the same as before fragmentation existed.
+pub fn split(data: &[u8], size: usize) -> Result<Vec<Vec<u8>>> {
+    let size = size.clamp(1, Chunk::MAX_LENGTH as usize - HEADER_LENGTH);
+    if data.len() <= size {
+        return Ok(vec![data.to_vec()]);
+    }
+
+    let total = u32::try_from(data.len().div_ceil(size)).map_err(|_| Error::MessageTooLarge {
+        size: data.len(),
+        capacity: (u32::MAX as usize).saturating_mul(size),
+    })?;
+    let id: u32 = rand::random();
+    Ok(data
+        .chunks(size)
+        .enumerate()
+        .map(|(index, piece)| {
+            let mut bytes = Vec::with_capacity(HEADER_LENGTH + piece.len());
+            bytes.extend_from_slice(&MAGIC);
+            bytes.extend(id.to_be_bytes());
+            bytes.extend((index as u32).to_be_bytes());
+            bytes.extend(total.to_be_bytes());
+            bytes.extend_from_slice(piece);
+            bytes
+        })
+        .collect())
+}
+
+/// Reassembles a payload from the contents of every chunk that may hold part
+/// of it, in file order. The first piece decides: a piece without a fragment
+/// header is the whole payload, while a fragment is joined with the others
+/// sharing its payload id, which can appear in any order.
+pub fn join<'a, I>(pieces: I) -> Result<Option<Vec<u8>>>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut pieces = pieces.into_iter();
+    let Some(first) = pieces.next() else {
+        return Ok(None);
+    };
+    let Some(Fragment { id, total, .. }) = Fragment::parse(first) else {
+        return Ok(Some(first.to_vec()));
+    };
+
+    let fragments = std::iter::once(first)
+        .chain(pieces)
+        .filter_map(Fragment::parse)
+        .filter(|fragment| fragment.id == id);
+    assemble(total, fragments).map(Some)
+}
+
+/// Reassembles every payload from the contents of every chunk that may hold
+/// one, in order of first appearance. Pieces without a fragment header are
+/// whole payloads; fragments are grouped by payload id. Each payload succeeds
+/// or fails on its own, so one with missing fragments does not hide the rest.
+pub fn join_all<'a, I>(pieces: I) -> Vec<Result<Vec<u8>>>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    enum Entry<'a> {
+        Whole(&'a [u8]),
+        Fragmented(u32),
+    }
+
+    let mut entries = Vec::new();
+    let mut groups: HashMap<u32, Vec<Fragment>> = HashMap::new();
+    for piece in pieces {
+        match Fragment::parse(piece) {
+            Some(fragment) => {
+                let group = groups.entry(fragment.id).or_default();
+                if group.is_empty() {
+                    entries.push(Entry::Fragmented(fragment.id));
+                }
+                group.push(fragment);
+            }
+            None => entries.push(Entry::Whole(piece)),
+        }
+    }
+
+    entries
+        .into_iter()
+        .map(|entry| match entry {
+            Entry::Whole(data) => Ok(data.to_vec()),
+            Entry::Fragmented(id) => {
+                let fragments = groups.remove(&id).unwrap_or_default();
+                let total = fragments.first().map_or(0, |fragment| fragment.total);
+                assemble(total, fragments)
+            }
+        })
+        .collect()
+}
+
+/// Joins the fragments of one payload, which must all be present once.
+fn assemble<'a, I>(total: u32, fragments: I) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = Fragment<'a>>,
+{
+    let mut received = BTreeMap::new();
+    for fragment in fragments {
+        if fragment.total != total || fragment.index >= total {
+            return Err(Error::InvalidHeader("Fragments disagree on the total."));
+        }
+        if received.insert(fragment.index, fragment.data).is_some() {
+            return Err(Error::DuplicateFragment {
+                index: fragment.index,
+            });
+        }
+    }
+
+    if received.len() as u32 != total {
+        return Err(Error::MissingFragments {
+            total,
+            received: received.len() as u32,
+            missing: (0..total)
+                .filter(|index| !received.contains_key(index))
+                .take(MAX_REPORTED)
+                .collect(),
+        });
+    }
+    Ok(received.into_values().flatten().copied().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data() -> Vec<u8> {
+        (0..=255).cycle().take(1000).collect()
+    }
+
+    #[test]
+    fn test_small_payload_is_not_fragmented() {
+        let pieces = split(b"hello", 5).unwrap();
+        assert_eq!(pieces, vec![b"hello".to_vec()]);
+        assert_eq!(
+            join(pieces.iter().map(Vec::as_slice)).unwrap(),
+            Some(b"hello".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_split_join() {
+        let pieces = split(&data(), 300).unwrap();
+        assert_eq!(pieces.len(), 4);
+        assert!(pieces.iter().all(|piece| piece.starts_with(&MAGIC)));
+        assert_eq!(pieces[3].len(), HEADER_LENGTH + 100);
+
+        let joined = join(pieces.iter().rev().map(Vec::as_slice)).unwrap();
+        assert_eq!(joined, Some(data()));
+    }
+
+    #[test]
+    fn test_join_ignores_other_payloads() {
+        let mut pieces = split(&data(), 300).unwrap();
+        pieces.insert(2, b"unrelated".to_vec());
+        pieces.extend(split(&[1; 1000], 300).unwrap());
+        let joined = join(pieces.iter().map(Vec::as_slice)).unwrap();
+        assert_eq!(joined, Some(data()));
+    }
+
+    #[test]
+    fn test_join_takes_first_payload() {
+        let mut pieces = split(&data(), 300).unwrap();
+        pieces.insert(0, b"whole".to_vec());
+        let joined = join(pieces.iter().map(Vec::as_slice)).unwrap();
+        assert_eq!(joined, Some(b"whole".to_vec()));
+    }
+
+    #[test]
+    fn test_missing_fragments() {
+        let mut pieces = split(&data(), 100).unwrap();
+        pieces.remove(7);
+        pieces.remove(2);
+        assert!(matches!(
+            join(pieces.iter().map(Vec::as_slice)),
+            Err(Error::MissingFragments { total: 10, received: 8, missing }) if missing == [2, 7]
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_fragment() {
+        let mut pieces = split(&data(), 300).unwrap();
+        pieces.push(pieces[1].clone());
+        assert!(matches!(
+            join(pieces.iter().map(Vec::as_slice)),
+            Err(Error::DuplicateFragment { index: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_join_all() {
+        let mut pieces = split(&data(), 300).unwrap();
+        pieces.insert(1, b"first".to_vec());
+        pieces.extend(split(&[1; 1000], 300).unwrap());
+        pieces.swap(2, 6);
+        pieces.push(b"last".to_vec());
+        let joined = join_all(pieces.iter().map(Vec::as_slice))
+            .into_iter()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            joined,
+            vec![data(), b"first".to_vec(), vec![1; 1000], b"last".to_vec()]
+        );
+        assert!(join_all(std::iter::empty()).is_empty());
+    }
+
+    #[test]
+    fn test_join_all_missing_fragments() {
+        let mut pieces = split(&data(), 300).unwrap();
+        pieces.remove(0);
+        pieces.insert(0, b"whole".to_vec());
+        pieces.extend(split(&[1; 1000], 300).unwrap());
+        let mut joined = join_all(pieces.iter().map(Vec::as_slice)).into_iter();
+        assert_eq!(joined.next().unwrap().unwrap(), b"whole");
+        assert!(matches!(
+            joined.next().unwrap(),
+            Err(Error::MissingFragments { missing, .. }) if missing == [0]
+        ));
+        assert_eq!(joined.next().unwrap().unwrap(), vec![1; 1000]);
+        assert!(joined.next().is_none());
+    }
+
+    #[test]
+    fn test_join_nothing() {
+        assert_eq!(join(std::iter::empty()).unwrap(), None);
+    }
+}
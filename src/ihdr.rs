@@ -0,0 +1,330 @@
+use crate::{chunk::Chunk, chunk_type::ChunkType, Error, Result};
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+/// Largest width or height allowed by the PNG specification.
+const MAX_DIMENSION: u32 = (1 << 31) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale = 0,
+    Truecolor = 2,
+    Indexed = 3,
+    GrayscaleAlpha = 4,
+    TruecolorAlpha = 6,
+}
+
+impl ColorType {
+    /// Number of samples that make up one pixel.
+    pub fn channels(&self) -> u8 {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Truecolor => 3,
+            ColorType::TruecolorAlpha => 4,
+        }
+    }
+
+    pub fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Truecolor | ColorType::GrayscaleAlpha | ColorType::TruecolorAlpha => {
+                &[8, 16]
+            }
+        }
+    }
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Truecolor),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::TruecolorAlpha),
+            _ => Err(Error::InvalidIhdr("Unknown color type.")),
+        }
+    }
+}
+
+impl fmt::Display for ColorType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Truecolor => "truecolor",
+            ColorType::Indexed => "indexed-color",
+            ColorType::GrayscaleAlpha => "grayscale with alpha",
+            ColorType::TruecolorAlpha => "truecolor with alpha",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterlaceMethod {
+    None = 0,
+    Adam7 = 1,
+}
+
+impl TryFrom<u8> for InterlaceMethod {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(InterlaceMethod::None),
+            1 => Ok(InterlaceMethod::Adam7),
+            _ => Err(Error::InvalidIhdr("Unknown interlace method.")),
+        }
+    }
+}
+
+/// The image header, which is always the first chunk of a PNG file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: ColorType,
+    compression_method: u8,
+    filter_method: u8,
+    interlace_method: InterlaceMethod,
+}
+
+impl Ihdr {
+    pub const LENGTH: usize = 13;
+
+    pub fn new(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: ColorType,
+        interlace_method: InterlaceMethod,
+    ) -> Result<Ihdr> {
+        if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(Error::InvalidIhdr("Invalid image dimensions."));
+        }
+        if !color_type.allowed_bit_depths().contains(&bit_depth) {
+            return Err(Error::InvalidIhdr(
+                "Bit depth is not allowed for the color type.",
+            ));
+        }
+
+        Ok(Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    pub fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    pub fn compression_method(&self) -> u8 {
+        self.compression_method
+    }
+
+    pub fn filter_method(&self) -> u8 {
+        self.filter_method
+    }
+
+    pub fn interlace_method(&self) -> InterlaceMethod {
+        self.interlace_method
+    }
+
+    pub fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() as usize * self.bit_depth as usize
+    }
+
+    /// Number of bytes per complete pixel, rounded up to one. This is the
+    /// distance used by the scanline filters.
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.bits_per_pixel().div_ceil(8)
+    }
+
+    /// Length of one scanline of a non-interlaced image in bytes, not counting
+    /// the filter type byte.
+    pub fn stride(&self) -> usize {
+        (self.width as usize * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    /// Total number of samples in the image, saturating for headers that
+    /// describe images too large to address.
+    pub fn samples(&self) -> usize {
+        (self.width as usize)
+            .saturating_mul(self.height as usize)
+            .saturating_mul(self.color_type.channels() as usize)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Ihdr::LENGTH);
+        bytes.extend(self.width.to_be_bytes());
+        bytes.extend(self.height.to_be_bytes());
+        bytes.push(self.bit_depth);
+        bytes.push(self.color_type as u8);
+        bytes.push(self.compression_method);
+        bytes.push(self.filter_method);
+        bytes.push(self.interlace_method as u8);
+        bytes
+    }
+
+    pub fn as_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::from_str("IHDR").unwrap(), self.as_bytes())
+    }
+}
+
+impl TryFrom<&[u8]> for Ihdr {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != Ihdr::LENGTH {
+            return Err(Error::InvalidIhdr("Length needs to be 13."));
+        }
+
+        let width = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
+        let height = u32::from_be_bytes(bytes[4..8].try_into().unwrap());
+        let ihdr = Ihdr::new(
+            width,
+            height,
+            bytes[8],
+            ColorType::try_from(bytes[9])?,
+            InterlaceMethod::try_from(bytes[12])?,
+        )?;
+
+        if bytes[10] != 0 {
+            return Err(Error::InvalidIhdr("Unknown compression method."));
+        }
+        if bytes[11] != 0 {
+            return Err(Error::InvalidIhdr("Unknown filter method."));
+        }
+
+        Ok(ihdr)
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        if chunk.chunk_type().to_string() != "IHDR" {
+            return Err(Error::InvalidIhdr("Wrong chunk type."));
+        }
+        Ihdr::try_from(chunk.data())
+    }
+}
+
+impl fmt::Display for Ihdr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}x{}, {}-bit {}, {}",
+            self.width,
+            self.height,
+            self.bit_depth,
+            self.color_type,
+            match self.interlace_method {
+                InterlaceMethod::None => "non-interlaced",
+                InterlaceMethod::Adam7 => "Adam7 interlaced",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_bytes() -> Vec<u8> {
+        vec![0, 0, 2, 159, 0, 0, 1, 192, 8, 2, 0, 0, 0]
+    }
+
+    #[test]
+    fn test_ihdr_from_bytes() {
+        let ihdr = Ihdr::try_from(&testing_bytes()[..]).unwrap();
+        assert_eq!(ihdr.width(), 671);
+        assert_eq!(ihdr.height(), 448);
+        assert_eq!(ihdr.bit_depth(), 8);
+        assert_eq!(ihdr.color_type(), ColorType::Truecolor);
+        assert_eq!(ihdr.compression_method(), 0);
+        assert_eq!(ihdr.filter_method(), 0);
+        assert_eq!(ihdr.interlace_method(), InterlaceMethod::None);
+    }
+
+    #[test]
+    fn test_ihdr_as_bytes() {
+        let ihdr = Ihdr::try_from(&testing_bytes()[..]).unwrap();
+        assert_eq!(ihdr.as_bytes(), testing_bytes());
+        assert_eq!(Ihdr::try_from(&ihdr.as_chunk()).unwrap(), ihdr);
+    }
+
+    #[test]
+    fn test_ihdr_derived_sizes() {
+        let ihdr = Ihdr::try_from(&testing_bytes()[..]).unwrap();
+        assert_eq!(ihdr.bits_per_pixel(), 24);
+        assert_eq!(ihdr.bytes_per_pixel(), 3);
+        assert_eq!(ihdr.stride(), 671 * 3);
+        assert_eq!(ihdr.samples(), 671 * 448 * 3);
+
+        let ihdr = Ihdr::new(3, 1, 2, ColorType::Grayscale, InterlaceMethod::None).unwrap();
+        assert_eq!(ihdr.bytes_per_pixel(), 1);
+        assert_eq!(ihdr.stride(), 1);
+    }
+
+    #[test]
+    fn test_invalid_bit_depth() {
+        assert!(Ihdr::new(1, 1, 4, ColorType::Truecolor, InterlaceMethod::None).is_err());
+        assert!(Ihdr::new(1, 1, 16, ColorType::Indexed, InterlaceMethod::None).is_err());
+        assert!(Ihdr::new(1, 1, 3, ColorType::Grayscale, InterlaceMethod::None).is_err());
+        assert!(Ihdr::new(1, 1, 16, ColorType::GrayscaleAlpha, InterlaceMethod::None).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_fields() {
+        let mut bytes = testing_bytes();
+        bytes[9] = 5;
+        assert!(Ihdr::try_from(&bytes[..]).is_err());
+
+        let mut bytes = testing_bytes();
+        bytes[10] = 1;
+        assert!(Ihdr::try_from(&bytes[..]).is_err());
+
+        let mut bytes = testing_bytes();
+        bytes[11] = 1;
+        assert!(Ihdr::try_from(&bytes[..]).is_err());
+
+        let mut bytes = testing_bytes();
+        bytes[12] = 2;
+        assert!(Ihdr::try_from(&bytes[..]).is_err());
+
+        let mut bytes = testing_bytes();
+        bytes[0..4].copy_from_slice(&[0, 0, 0, 0]);
+        assert!(Ihdr::try_from(&bytes[..]).is_err());
+
+        assert!(Ihdr::try_from(&testing_bytes()[..12]).is_err());
+    }
+
+    #[test]
+    fn test_ihdr_display() {
+        let ihdr = Ihdr::try_from(&testing_bytes()[..]).unwrap();
+        assert_eq!(ihdr.to_string(), "671x448, 8-bit truecolor, non-interlaced");
+    }
+}
@@ -0,0 +1,190 @@
+use crate::{chunk::Chunk, ihdr::Ihdr, png::Png, text::TextChunk};
+use serde::Serialize;
+use std::fmt;
+
+/// Longest text or number of data bytes shown in a summary.
+const PREVIEW_LENGTH: usize = 48;
+
+/// What `print` shows about one chunk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChunkInfo {
+    /// Position of the chunk's length field from the start of the file.
+    pub offset: u64,
+    pub chunk_type: String,
+    pub length: u32,
+    pub crc: u32,
+    pub critical: bool,
+    pub public: bool,
+    pub safe_to_copy: bool,
+    /// The decoded contents of known chunk types, or a hex preview.
+    pub summary: String,
+}
+
+impl ChunkInfo {
+    fn new(chunk: &Chunk, offset: u64) -> ChunkInfo {
+        let chunk_type = chunk.chunk_type();
+        ChunkInfo {
+            offset,
+            chunk_type: chunk_type.to_string(),
+            length: chunk.length(),
+            crc: chunk.crc(),
+            critical: chunk_type.is_critical(),
+            public: chunk_type.is_public(),
+            safe_to_copy: chunk_type.is_safe_to_copy(),
+            summary: summarize(chunk),
+        }
+    }
+}
+
+impl fmt::Display for ChunkInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flag = |set: bool, letter: char| if set { letter } else { '-' };
+        write!(
+            f,
+            "{:>10}  {}  {:>10}  {:08x}  {}{}{}  {}",
+            self.offset,
+            self.chunk_type,
+            self.length,
+            self.crc,
+            flag(self.critical, 'C'),
+            flag(self.public, 'P'),
+            flag(self.safe_to_copy, 'S'),
+            self.summary
+        )
+    }
+}
+
+/// Describes every chunk of `png`, in file order.
+pub fn inspect(png: &Png) -> Vec<ChunkInfo> {
+    let mut offset = Png::STANDARD_HEADER.len() as u64;
+    png.chunks()
+        .iter()
+        .map(|chunk| {
+            let info = ChunkInfo::new(chunk, offset);
+            offset += 12 + chunk.length() as u64;
+            info
+        })
+        .collect()
+}
+
+fn summarize(chunk: &Chunk) -> String {
+    let data = chunk.data();
+    let field = |start: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(
+            data.get(start..start + 4)?.try_into().ok()?,
+        ))
+    };
+    let summary = match chunk.chunk_type().to_string().as_str() {
+        "IHDR" => Ihdr::try_from(chunk).ok().map(|ihdr| ihdr.to_string()),
+        "PLTE" if data.len().is_multiple_of(3) => {
+            Some(format!("{} palette entries", data.len() / 3))
+        }
+        "IDAT" => Some("compressed image data".to_string()),
+        "IEND" if data.is_empty() => Some(String::new()),
+        "gAMA" if data.len() == 4 => field(0).map(|gamma| format!("gamma {}", gamma as f64 / 1e5)),
+        "sRGB" if data.len() == 1 => {
+            let intents = [
+                "perceptual",
+                "relative colorimetric",
+                "saturation",
+                "absolute colorimetric",
+            ];
+            intents
+                .get(data[0] as usize)
+                .map(|intent| format!("{} rendering intent", intent))
+        }
+        "pHYs" if data.len() == 9 => {
+            let unit = if data[8] == 1 { " per metre" } else { "" };
+            field(0)
+                .zip(field(4))
+                .map(|(x, y)| format!("{}x{} pixels{}", x, y, unit))
+        }
+        "tIME" if data.len() == 7 => Some(format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            u16::from_be_bytes([data[0], data[1]]),
+            data[2],
+            data[3],
+            data[4],
+            data[5],
+            data[6]
+        )),
+        "tEXt" | "zTXt" | "iTXt" => TextChunk::try_from(chunk)
+            .ok()
+            .map(|text| truncate(&text.to_string())),
+        _ => None,
+    };
+    summary.unwrap_or_else(|| hex_preview(data))
+}
+
+fn truncate(text: &str) -> String {
+    let text = text.escape_debug().to_string();
+    match text.char_indices().nth(PREVIEW_LENGTH) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    }
+}
+
+fn hex_preview(data: &[u8]) -> String {
+    let mut preview: Vec<String> = data
+        .iter()
+        .take(PREVIEW_LENGTH / 3)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    if data.len() > PREVIEW_LENGTH / 3 {
+        preview.push("...".to_string());
+    }
+    preview.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk_type::ChunkType, text::Text};
+    use std::str::FromStr;
+
+    const PNG_FILE: &[u8] = include_bytes!("../image/dice.png");
+
+    #[test]
+    fn test_inspect() {
+        let png = Png::try_from(PNG_FILE).unwrap();
+        let infos = inspect(&png);
+        assert_eq!(infos.len(), png.chunks().len());
+        assert_eq!(infos[0].offset, 8);
+        assert_eq!(infos[0].chunk_type, "IHDR");
+        assert_eq!(infos[0].summary, "671x448, 8-bit truecolor, non-interlaced");
+        assert!(infos[0].critical && infos[0].public && !infos[0].safe_to_copy);
+        assert_eq!(infos[1].offset, 8 + 12 + 13);
+
+        let last = infos.last().unwrap();
+        assert_eq!(last.chunk_type, "IEND");
+        assert_eq!(last.offset as usize, PNG_FILE.len() - 12);
+    }
+
+    #[test]
+    fn test_summaries() {
+        let text = Text::new("Comment", "hello").unwrap().as_chunk();
+        assert_eq!(summarize(&text), "Comment: hello");
+
+        let binary = Chunk::new(ChunkType::from_str("ruSt").unwrap(), (0..20).collect());
+        assert_eq!(
+            summarize(&binary),
+            "00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f ..."
+        );
+
+        let time = Chunk::new(
+            ChunkType::from_str("tIME").unwrap(),
+            vec![7, 234, 10, 18, 9, 5, 0],
+        );
+        assert_eq!(summarize(&time), "2026-10-18 09:05:00");
+
+        let long = Text::new("Comment", &"x".repeat(100)).unwrap().as_chunk();
+        assert!(summarize(&long).ends_with("x..."));
+    }
+
+    #[test]
+    fn test_json() {
+        let png = Png::try_from(PNG_FILE).unwrap();
+        let json = serde_json::to_string(&inspect(&png)[0]).unwrap();
+        assert!(json.starts_with(r#"{"offset":8,"chunk_type":"IHDR","length":13,"#));
+    }
+}
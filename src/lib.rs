@@ -0,0 +1,37 @@
+//! Hide messages in PNG files.
+//!
+//! Files are parsed into a [`Png`] made of [`Chunk`]s, either all at once with
+//! [`Png::try_from`] or one chunk at a time with [`stream::ChunkReader`].
+//! Messages can be stored in chunks of their own, or in the pixels themselves
+//! with [`lsb::embed`] and [`lsb::extract`] or in the choice between similar
+//! palette colors with [`palette::embed`], and optionally encrypted with
+//! [`crypto::encrypt`] first. Files are wrapped in a [`payload::Payload`] so
+//! their name and MIME type survive the trip. [`decoder::decode`] turns the
+//! image data into pixels, and [`encoder::write_pixels`] stores them back.
+
+pub mod analyze;
+pub mod chunk;
+pub mod chunk_type;
+pub mod compression;
+pub mod crypto;
+pub mod decoder;
+pub mod deflate;
+pub mod encoder;
+pub mod error;
+pub mod fragment;
+pub mod ihdr;
+pub mod inspect;
+pub mod lsb;
+pub mod palette;
+pub mod payload;
+pub mod png;
+pub mod stream;
+pub mod text;
+pub mod validate;
+
+pub use chunk::Chunk;
+pub use chunk_type::ChunkType;
+pub use error::Error;
+pub use png::Png;
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,539 @@
+use crate::{
+    decoder::Scanlines,
+    ihdr::{ColorType, Ihdr, InterlaceMethod},
+    png::Png,
+    Error, Result,
+};
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::fmt;
+
+/// Number of bytes used to store the payload length in front of the payload.
+const LENGTH_PREFIX: usize = 4;
+
+/// Largest number of payload bits per block of the Hamming code.
+pub const MAX_CODE: u8 = 16;
+
+/// Settings for [`embed`] and [`extract`], which must match between the two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LsbOptions {
+    /// Number of low bits of every sample that carry the payload.
+    pub bits: u8,
+    /// Seed of the order in which the bits are used, such as one from
+    /// [`crate::crypto::derive_seed`]. Without it the payload fills the image
+    /// from the top, which is easy to spot; with it the payload is spread
+    /// over the whole image and cannot be found without the seed.
+    pub key: Option<[u8; 32]>,
+    /// Number of payload bits `k` hidden in every block of `2^k - 1` carrier
+    /// bits with a Hamming code, by changing at most one bit of the block.
+    /// Higher values change fewer pixels but hold less; 1 stores one payload
+    /// bit in every carrier bit.
+    pub code: u8,
+}
+
+impl Default for LsbOptions {
+    fn default() -> LsbOptions {
+        LsbOptions {
+            bits: 1,
+            key: None,
+            code: 1,
+        }
+    }
+}
+
+impl LsbOptions {
+    /// Number of carrier bits in a block of the Hamming code.
+    fn block_length(&self) -> usize {
+        (1 << self.code) - 1
+    }
+}
+
+/// What [`embed`] did to the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Embedding {
+    /// Number of bits hidden, including the length in front of the payload.
+    pub payload_bits: usize,
+    /// Number of carrier bits the payload was spread over.
+    pub carrier_bits: usize,
+    /// Number of carrier bits that had to be flipped.
+    pub changes: usize,
+}
+
+impl Embedding {
+    /// Payload bits hidden per changed bit. Plain LSB embedding averages 2,
+    /// since half the carrier bits already hold the right value. A code of
+    /// `k` raises that to `k / (1 - 2^-k)`.
+    pub fn efficiency(&self) -> f64 {
+        self.payload_bits as f64 / self.changes.max(1) as f64
+    }
+}
+
+impl fmt::Display for Embedding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Hid {} bits in {} carrier bits by changing {}, {:.2} bits per change.",
+            self.payload_bits,
+            self.carrier_bits,
+            self.changes,
+            self.efficiency()
+        )
+    }
+}
+
+/// Checks that the pixels of the image can carry a payload with `options`.
+fn check_supported(ihdr: &Ihdr, options: &LsbOptions) -> Result<()> {
+    if ihdr.color_type() == ColorType::Indexed {
+        return Err(Error::Unsupported(
+            "Indexed-color images are not supported.",
+        ));
+    }
+    if ihdr.interlace_method() != InterlaceMethod::None {
+        return Err(Error::Unsupported("Interlaced images are not supported."));
+    }
+    if options.bits == 0 || options.bits > ihdr.bit_depth() {
+        return Err(Error::Unsupported(
+            "Bits per sample must be between 1 and the bit depth.",
+        ));
+    }
+    if options.code == 0 || options.code > MAX_CODE {
+        return Err(Error::Unsupported(
+            "Hamming code must hide between 1 and 16 bits per block.",
+        ));
+    }
+    Ok(())
+}
+
+/// Number of payload bytes that fit in the lowest `options.bits` bits of
+/// every sample, after coding.
+fn max_payload(ihdr: &Ihdr, options: &LsbOptions) -> usize {
+    let carrier_bits = ihdr.samples().saturating_mul(options.bits as usize);
+    let blocks = carrier_bits / options.block_length();
+    (blocks * options.code as usize / 8).saturating_sub(LENGTH_PREFIX)
+}
+
+/// Maps the index of a carrier bit, counting `bits` bits per sample in
+/// sample order, least significant first, to a byte index into the
+/// unfiltered image data and a shift within that byte.
+fn lsb_position(ihdr: &Ihdr, bits: u8) -> impl Fn(usize) -> (usize, u8) {
+    let bits = bits as usize;
+    let depth = ihdr.bit_depth() as usize;
+    let stride = ihdr.stride();
+    let samples_per_row = ihdr.width() as usize * ihdr.color_type().channels() as usize;
+    move |index| {
+        let (sample, significance) = (index / bits, index % bits);
+        let (row, column) = (sample / samples_per_row, sample % samples_per_row);
+        let bit = column * depth + depth - 1 - significance;
+        (row * stride + bit / 8, 7 - (bit % 8) as u8)
+    }
+}
+
+/// Position of the lowest `bits` bits of every sample, as given by
+/// [`lsb_position`], in sample order.
+fn lsb_positions(ihdr: &Ihdr, bits: u8) -> impl Iterator<Item = (usize, u8)> {
+    let carrier_bits = ihdr.samples().saturating_mul(bits as usize);
+    (0..carrier_bits).map(lsb_position(ihdr, bits))
+}
+
+/// The positions of [`lsb_positions`] in the order the payload uses them: as
+/// they are, or shuffled by a ChaCha20 generator seeded with the key. Only
+/// the carrier bit indices are shuffled, as 4 bytes each, and turned into
+/// positions as they are used.
+fn ordered_positions(
+    ihdr: &Ihdr,
+    options: &LsbOptions,
+) -> Result<Box<dyn Iterator<Item = (usize, u8)>>> {
+    let Some(key) = options.key else {
+        return Ok(Box::new(lsb_positions(ihdr, options.bits)));
+    };
+    let carrier_bits = u32::try_from(ihdr.samples().saturating_mul(options.bits as usize))
+        .map_err(|_| Error::Unsupported("Image is too large to scatter the payload over."))?;
+    let mut indices: Vec<u32> = (0..carrier_bits).collect();
+    indices.shuffle(&mut ChaCha20Rng::from_seed(key));
+    let position = lsb_position(ihdr, options.bits);
+    Ok(Box::new(
+        indices
+            .into_iter()
+            .map(move |index| position(index as usize)),
+    ))
+}
+
+/// Groups `positions` into blocks of `length` for the Hamming code, dropping
+/// a short last block.
+fn blocks<I>(mut positions: I, length: usize) -> impl Iterator<Item = Vec<(usize, u8)>>
+where
+    I: Iterator<Item = (usize, u8)>,
+{
+    std::iter::from_fn(move || {
+        let block: Vec<(usize, u8)> = positions.by_ref().take(length).collect();
+        (block.len() == length).then_some(block)
+    })
+}
+
+/// The bits a block of carrier bits stands for: the XOR of the 1-based
+/// positions of its set bits, which is the syndrome of the Hamming code.
+fn syndrome(data: &[u8], block: &[(usize, u8)]) -> usize {
+    block
+        .iter()
+        .enumerate()
+        .filter(|(_, &(index, shift))| (data[index] >> shift) & 1 == 1)
+        .fold(0, |syndrome, (position, _)| syndrome ^ (position + 1))
+}
+
+/// Number of payload bytes that [`embed`] can hide in the image with
+/// `options`.
+pub fn capacity(png: &Png, options: &LsbOptions) -> Result<usize> {
+    let ihdr = png.ihdr()?;
+    check_supported(&ihdr, options)?;
+    Scanlines::decode(png, &ihdr)?;
+    Ok(max_payload(&ihdr, options))
+}
+
+/// Hides `payload` in the lowest `options.bits` bits of every sample,
+/// replacing the image data of `png` with the modified pixels.
+pub fn embed(png: &mut Png, payload: &[u8], options: &LsbOptions) -> Result<Embedding> {
+    let ihdr = png.ihdr()?;
+    check_supported(&ihdr, options)?;
+    let capacity = max_payload(&ihdr, options);
+    if payload.len() > capacity {
+        return Err(Error::MessageTooLarge {
+            size: payload.len(),
+            capacity,
+        });
+    }
+    let mut scanlines = Scanlines::decode(png, &ihdr)?;
+
+    let length = (payload.len() as u32).to_be_bytes();
+    let payload_bits: Vec<u8> = length
+        .iter()
+        .chain(payload)
+        .flat_map(|byte| (0..8).rev().map(move |shift| (byte >> shift) & 1))
+        .collect();
+    let code = options.code as usize;
+    let positions = ordered_positions(&ihdr, options)?;
+    let mut embedding = Embedding {
+        payload_bits: payload_bits.len(),
+        carrier_bits: 0,
+        changes: 0,
+    };
+    for (block, bits) in blocks(positions, options.block_length()).zip(payload_bits.chunks(code)) {
+        // A short last group is padded with zeros.
+        let wanted = bits
+            .iter()
+            .fold(0, |value, &bit| (value << 1) | bit as usize)
+            << (code - bits.len());
+        let flip = syndrome(&scanlines.data, &block) ^ wanted;
+        if flip != 0 {
+            let (index, shift) = block[flip - 1];
+            scanlines.data[index] ^= 1 << shift;
+            embedding.changes += 1;
+        }
+        embedding.carrier_bits += block.len();
+    }
+
+    png.replace_image_data(&scanlines.encode(&ihdr)?);
+    Ok(embedding)
+}
+
+/// Recovers a payload hidden with [`embed`] using the same options.
+pub fn extract(png: &Png, options: &LsbOptions) -> Result<Vec<u8>> {
+    let ihdr = png.ihdr()?;
+    check_supported(&ihdr, options)?;
+    let scanlines = Scanlines::decode(png, &ihdr)?;
+
+    let code = options.code;
+    let mut bytes = blocks(ordered_positions(&ihdr, options)?, options.block_length())
+        .flat_map(|block| {
+            let value = syndrome(&scanlines.data, &block);
+            (0..code).rev().map(move |shift| (value >> shift) as u8 & 1)
+        })
+        .collect::<Vec<u8>>()
+        .chunks_exact(8)
+        .map(|bits| bits.iter().fold(0, |byte, bit| (byte << 1) | bit))
+        .collect::<Vec<u8>>();
+
+    if bytes.len() < LENGTH_PREFIX {
+        return Err(Error::MessageNotFound);
+    }
+    let length = u32::from_be_bytes(bytes[..LENGTH_PREFIX].try_into().unwrap()) as usize;
+    if length > bytes.len() - LENGTH_PREFIX {
+        return Err(Error::MessageNotFound);
+    }
+    bytes.truncate(LENGTH_PREFIX + length);
+    Ok(bytes.split_off(LENGTH_PREFIX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        Png::try_from(&include_bytes!("../image/dice.png")[..]).unwrap()
+    }
+
+    fn options(bits: u8) -> LsbOptions {
+        LsbOptions {
+            bits,
+            ..LsbOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_embed_extract() {
+        let mut png = testing_png();
+        embed(
+            &mut png,
+            b"This is where your secret message will be!",
+            &options(1),
+        )
+        .unwrap();
+        let png = Png::try_from(&png.as_bytes()[..]).unwrap();
+        assert_eq!(
+            extract(&png, &options(1)).unwrap(),
+            b"This is where your secret message will be!"
+        );
+    }
+
+    #[test]
+    fn test_embed_only_changes_lsb() {
+        let original = testing_png();
+        let mut png = testing_png();
+        embed(&mut png, &[0xA5; 512], &options(1)).unwrap();
+
+        let ihdr = png.ihdr().unwrap();
+        let before = Scanlines::decode(&original, &ihdr).unwrap();
+        let after = Scanlines::decode(&png, &ihdr).unwrap();
+        assert_eq!(before.filters, after.filters);
+        assert!(before
+            .data
+            .iter()
+            .zip(after.data.iter())
+            .all(|(before, after)| before >> 1 == after >> 1));
+        assert_ne!(before.data, after.data);
+    }
+
+    #[test]
+    fn test_embed_extract_bits() {
+        let original = testing_png();
+        let message: Vec<u8> = (0..=255).cycle().take(20_000).collect();
+        for bits in [2, 3, 8] {
+            let mut png = testing_png();
+            embed(&mut png, &message, &options(bits)).unwrap();
+            assert_eq!(extract(&png, &options(bits)).unwrap(), message);
+
+            let ihdr = png.ihdr().unwrap();
+            let before = Scanlines::decode(&original, &ihdr).unwrap();
+            let after = Scanlines::decode(&png, &ihdr).unwrap();
+            assert!(before
+                .data
+                .iter()
+                .zip(after.data.iter())
+                .all(
+                    |(before, after)| before.checked_shr(bits as u32).unwrap_or(0)
+                        == after.checked_shr(bits as u32).unwrap_or(0)
+                ));
+        }
+        assert!(embed(&mut testing_png(), b"message", &options(9)).is_err());
+        assert!(embed(&mut testing_png(), b"message", &options(0)).is_err());
+    }
+
+    #[test]
+    fn test_embed_keeps_ancillary_chunks() {
+        let mut png = testing_png();
+        embed(&mut png, b"Message", &options(1)).unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .filter(|chunk_type| chunk_type != "IDAT")
+            .collect();
+        assert_eq!(types, ["IHDR", "gAMA", "tEXt", "tIME", "IEND"]);
+        assert_eq!(png.chunks()[3].chunk_type().to_string(), "IDAT");
+    }
+
+    #[test]
+    fn test_key_scatters_payload() {
+        let original = testing_png();
+        let keyed = LsbOptions {
+            key: Some([7; 32]),
+            ..LsbOptions::default()
+        };
+        let mut png = testing_png();
+        embed(&mut png, &[0xA5; 512], &keyed).unwrap();
+        assert_eq!(extract(&png, &keyed).unwrap(), [0xA5; 512]);
+
+        // The same payload in sequential order only reaches the first rows.
+        let ihdr = png.ihdr().unwrap();
+        let before = Scanlines::decode(&original, &ihdr).unwrap();
+        let after = Scanlines::decode(&png, &ihdr).unwrap();
+        let changed: Vec<usize> = (0..before.data.len())
+            .filter(|&i| before.data[i] != after.data[i])
+            .collect();
+        let half = before.data.len() / 2;
+        assert!(changed.iter().any(|&i| i < half));
+        assert!(changed.iter().any(|&i| i > half));
+    }
+
+    #[test]
+    fn test_key_order_is_stable() {
+        // Images scattered by earlier versions, which shuffled the positions
+        // themselves, must keep decoding.
+        let ihdr = testing_png().ihdr().unwrap();
+        let keyed = LsbOptions {
+            bits: 2,
+            key: Some([7; 32]),
+            ..LsbOptions::default()
+        };
+        let mut expected: Vec<(usize, u8)> = lsb_positions(&ihdr, 2).collect();
+        expected.shuffle(&mut ChaCha20Rng::from_seed([7; 32]));
+        let positions: Vec<(usize, u8)> = ordered_positions(&ihdr, &keyed).unwrap().collect();
+        assert_eq!(positions, expected);
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let mut png = testing_png();
+        let keyed = |byte| LsbOptions {
+            key: Some([byte; 32]),
+            ..LsbOptions::default()
+        };
+        embed(&mut png, b"Message", &keyed(1)).unwrap();
+        assert_eq!(extract(&png, &keyed(1)).unwrap(), b"Message");
+        assert!(extract(&png, &keyed(2)).is_err());
+        assert!(extract(&png, &options(1)).is_err());
+    }
+
+    #[test]
+    fn test_syndrome() {
+        let data = [0b1010_0000];
+        let block: Vec<(usize, u8)> = (5..8).rev().map(|shift| (0, shift)).collect();
+        // Bits 1, 0, 1 at positions 1, 2, 3.
+        assert_eq!(syndrome(&data, &block), 1 ^ 3);
+    }
+
+    #[test]
+    fn test_matrix_embedding() {
+        let original = testing_png();
+        let ihdr = original.ihdr().unwrap();
+        let message: Vec<u8> = (0..=255).cycle().take(5000).collect();
+        let mut changes = Vec::new();
+        for code in 1..=4 {
+            for key in [None, Some([3; 32])] {
+                let options = LsbOptions {
+                    code,
+                    key,
+                    ..LsbOptions::default()
+                };
+                let mut png = testing_png();
+                let embedding = embed(&mut png, &message, &options).unwrap();
+                assert_eq!(extract(&png, &options).unwrap(), message);
+                assert_eq!(embedding.payload_bits, (message.len() + LENGTH_PREFIX) * 8);
+
+                // Every block of 2^k - 1 carrier bits changes at most once.
+                let blocks = embedding.carrier_bits / options.block_length();
+                assert!(embedding.changes <= blocks);
+                let before = Scanlines::decode(&original, &ihdr).unwrap();
+                let after = Scanlines::decode(&png, &ihdr).unwrap();
+                let flipped: u32 = before
+                    .data
+                    .iter()
+                    .zip(&after.data)
+                    .map(|(before, after)| (before ^ after).count_ones())
+                    .sum();
+                assert_eq!(flipped as usize, embedding.changes);
+                if key.is_none() {
+                    changes.push(embedding);
+                }
+            }
+        }
+        assert!(changes
+            .windows(2)
+            .all(|pair| pair[1].changes < pair[0].changes));
+        assert!(changes[3].efficiency() > 3.0, "{:?}", changes[3]);
+    }
+
+    #[test]
+    fn test_code_must_match() {
+        let mut png = testing_png();
+        let coded = LsbOptions {
+            code: 3,
+            ..LsbOptions::default()
+        };
+        embed(&mut png, b"Message", &coded).unwrap();
+        assert!(extract(&png, &options(1)).is_err());
+        for code in [0, MAX_CODE + 1] {
+            let options = LsbOptions {
+                code,
+                ..LsbOptions::default()
+            };
+            assert!(embed(&mut png, b"Message", &options).is_err());
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        let png = testing_png();
+        assert_eq!(capacity(&png, &options(1)).unwrap(), 671 * 448 * 3 / 8 - 4);
+        assert_eq!(
+            capacity(&png, &options(3)).unwrap(),
+            671 * 448 * 3 * 3 / 8 - 4
+        );
+        assert!(capacity(&png, &options(9)).is_err());
+
+        let coded = LsbOptions {
+            code: 3,
+            ..LsbOptions::default()
+        };
+        let blocks = 671 * 448 * 3 / 7;
+        assert_eq!(capacity(&png, &coded).unwrap(), blocks * 3 / 8 - 4);
+    }
+
+    #[test]
+    fn test_message_too_large() {
+        let mut png = testing_png();
+        let message = vec![0; capacity(&png, &options(1)).unwrap() + 1];
+        assert!(embed(&mut png, &message, &options(1)).is_err());
+        assert!(embed(&mut png, &message, &options(2)).is_ok());
+    }
+
+    #[test]
+    fn test_extract_without_message() {
+        let png = testing_png();
+        assert!(extract(&png, &options(1)).is_err());
+    }
+
+    #[test]
+    fn test_sub_byte_lsb_positions() {
+        let ihdr = Ihdr::new(3, 2, 2, ColorType::Grayscale, InterlaceMethod::None).unwrap();
+        let positions: Vec<(usize, u8)> = lsb_positions(&ihdr, 1).collect();
+        assert_eq!(positions, [(0, 6), (0, 4), (0, 2), (1, 6), (1, 4), (1, 2)]);
+
+        let positions: Vec<(usize, u8)> = lsb_positions(&ihdr, 2).take(4).collect();
+        assert_eq!(positions, [(0, 6), (0, 7), (0, 4), (0, 5)]);
+    }
+
+    #[test]
+    fn test_header_does_not_match_image_data() {
+        let sizes = [
+            (1, 1),
+            (672, 448),
+            (671, 449),
+            (u32::MAX >> 1, u32::MAX >> 1),
+        ];
+        for (width, height) in sizes {
+            let mut png = testing_png();
+            let ihdr = Ihdr::new(
+                width,
+                height,
+                16,
+                ColorType::TruecolorAlpha,
+                InterlaceMethod::None,
+            )
+            .unwrap();
+            png.remove_first_chunk("IHDR").unwrap();
+            png.insert_chunk_before("gAMA", ihdr.as_chunk()).unwrap();
+            assert!(extract(&png, &options(1)).is_err());
+            assert!(embed(&mut png, b"message", &options(1)).is_err());
+        }
+    }
+}
@@ -1,19 +1,24 @@
 mod args;
-mod chunk;
-mod chunk_type;
 mod commands;
-mod png;
 use clap::Parser;
+use std::process::ExitCode;
 
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
-
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let args = args::Cli::parse();
-    match args.cmd {
+    let result = match args.cmd {
         args::Commands::Encode(args) => commands::encode(&args),
         args::Commands::Decode(args) => commands::decode(&args),
         args::Commands::Remove(args) => commands::remove(&args),
-        args::Commands::Print(args) => commands::print(&args.file_path),
+        args::Commands::Print(args) => commands::print(&args),
+        args::Commands::Validate(args) => commands::validate(&args),
+        args::Commands::Capacity(args) => commands::capacity(&args),
+        args::Commands::Analyze(args) => commands::analyze(&args),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(e.exit_code())
+        }
     }
 }
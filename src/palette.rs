@@ -0,0 +1,321 @@
+use crate::{
+    decoder::{self, Samples},
+    encoder::{self, EncodeOptions, FilterStrategy},
+    ihdr::ColorType,
+    png::Png,
+    Error, Result,
+};
+
+/// Number of bytes used to store the payload length in front of the payload.
+const LENGTH_PREFIX: usize = 4;
+
+/// Largest distance, as measured by [`distance`], between the two colors of
+/// a pair. Pixels of colors without a partner this close carry no bits.
+const MAX_DISTANCE: u32 = 48;
+
+/// Partner of a palette entry, and the bit a pixel of that entry stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Pair {
+    partner: u8,
+    bit: u8,
+}
+
+/// Perceptual distance between two colors with alpha, using the "redmean"
+/// approximation of how the eye weighs red, green and blue differences.
+fn distance(a: [u8; 4], b: [u8; 4]) -> u32 {
+    let mean_red = (a[0] as i32 + b[0] as i32) / 2;
+    let [red, green, blue, alpha] = [0, 1, 2, 3].map(|i| a[i] as i32 - b[i] as i32);
+    let squared = (((512 + mean_red) * red * red) >> 8)
+        + 4 * green * green
+        + (((767 - mean_red) * blue * blue) >> 8)
+        + 4 * alpha * alpha;
+    (squared as f64).sqrt() as u32
+}
+
+/// Pairs up palette entries, closest colors first. In every pair the entry
+/// with the lower index stands for 0 and the other for 1, so that swapping a
+/// pixel between the two entries changes its bit but barely its color.
+fn pairs(colors: &[[u8; 4]]) -> Vec<Option<Pair>> {
+    let mut candidates = Vec::new();
+    for a in 0..colors.len() {
+        for b in a + 1..colors.len() {
+            let distance = distance(colors[a], colors[b]);
+            if distance <= MAX_DISTANCE {
+                candidates.push((distance, a, b));
+            }
+        }
+    }
+    candidates.sort();
+
+    let mut pairs = vec![None; colors.len()];
+    for (_, a, b) in candidates {
+        if pairs[a].is_none() && pairs[b].is_none() {
+            pairs[a] = Some(Pair {
+                partner: b as u8,
+                bit: 0,
+            });
+            pairs[b] = Some(Pair {
+                partner: a as u8,
+                bit: 1,
+            });
+        }
+    }
+    pairs
+}
+
+/// The palette pairs and pixel indices of an indexed-color image.
+fn read(png: &Png) -> Result<(Vec<Option<Pair>>, Vec<u8>)> {
+    let image = decoder::decode(png)?;
+    if image.ihdr().color_type() != ColorType::Indexed {
+        return Err(Error::Unsupported(
+            "Palette mode needs an indexed-color image.",
+        ));
+    }
+    let alphas = png
+        .chunk_by_type("tRNS")
+        .map(|chunk| chunk.data())
+        .unwrap_or_default();
+    let colors: Vec<[u8; 4]> = image
+        .palette()
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+        .map(|(i, &[red, green, blue])| [red, green, blue, *alphas.get(i).unwrap_or(&255)])
+        .collect();
+
+    let Samples::Eight(indices) = image.samples() else {
+        return Err(Error::InvalidImageData(
+            "Indexed image with 16-bit samples.",
+        ));
+    };
+    if indices.iter().any(|&index| index as usize >= colors.len()) {
+        return Err(Error::InvalidImageData("Palette index out of range."));
+    }
+    Ok((pairs(&colors), indices.clone()))
+}
+
+/// Positions of the pixels that can carry a bit, in image order.
+fn carriers<'a>(pairs: &'a [Option<Pair>], indices: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+    (0..indices.len()).filter(|&i| pairs[indices[i] as usize].is_some())
+}
+
+fn max_payload(pairs: &[Option<Pair>], indices: &[u8]) -> usize {
+    (carriers(pairs, indices).count() / 8).saturating_sub(LENGTH_PREFIX)
+}
+
+/// Number of payload bytes that [`embed`] can hide in the image.
+pub fn capacity(png: &Png) -> Result<usize> {
+    let (pairs, indices) = read(png)?;
+    Ok(max_payload(&pairs, &indices))
+}
+
+/// Hides `payload` in an indexed-color image by moving pixels between the two
+/// entries of pairs of near-identical palette colors. The palette itself is
+/// left untouched.
+pub fn embed(png: &mut Png, payload: &[u8]) -> Result<()> {
+    let (pairs, mut indices) = read(png)?;
+    let capacity = max_payload(&pairs, &indices);
+    if payload.len() > capacity {
+        return Err(Error::MessageTooLarge {
+            size: payload.len(),
+            capacity,
+        });
+    }
+
+    let length = (payload.len() as u32).to_be_bytes();
+    let payload_bits = length
+        .iter()
+        .chain(payload)
+        .flat_map(|byte| (0..8).rev().map(move |shift| (byte >> shift) & 1));
+    let positions: Vec<usize> = carriers(&pairs, &indices).collect();
+    for (position, bit) in positions.into_iter().zip(payload_bits) {
+        let index = &mut indices[position];
+        if let Some(pair) = pairs[*index as usize] {
+            if pair.bit != bit {
+                *index = pair.partner;
+            }
+        }
+    }
+
+    // Indexed images compress best without prediction filters.
+    let ihdr = png.ihdr()?;
+    let options = EncodeOptions {
+        filter: FilterStrategy::Fixed(0),
+        level: 9,
+        ..EncodeOptions::default()
+    };
+    let data = encoder::encode(&ihdr, &Samples::Eight(indices), &options)?;
+    png.replace_image_data(&data);
+    Ok(())
+}
+
+/// Recovers a payload hidden with [`embed`].
+pub fn extract(png: &Png) -> Result<Vec<u8>> {
+    let (pairs, indices) = read(png)?;
+    let mut bytes = carriers(&pairs, &indices)
+        .filter_map(|position| pairs[indices[position] as usize].map(|pair| pair.bit))
+        .collect::<Vec<u8>>()
+        .chunks_exact(8)
+        .map(|bits| bits.iter().fold(0, |byte, bit| (byte << 1) | bit))
+        .collect::<Vec<u8>>();
+
+    if bytes.len() < LENGTH_PREFIX {
+        return Err(Error::MessageNotFound);
+    }
+    let length = u32::from_be_bytes(bytes[..LENGTH_PREFIX].try_into().unwrap()) as usize;
+    if length > bytes.len() - LENGTH_PREFIX {
+        return Err(Error::MessageNotFound);
+    }
+    bytes.truncate(LENGTH_PREFIX + length);
+    Ok(bytes.split_off(LENGTH_PREFIX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::Chunk, ihdr::Ihdr};
+
+    const WIDTH: u32 = 40;
+    const HEIGHT: u32 = 30;
+
+    /// 16 colors in 8 pairs of near-identical shades, plus one color far
+    /// from all others.
+    fn testing_palette() -> Vec<u8> {
+        let mut palette: Vec<u8> = (0..8u8)
+            .flat_map(|i| {
+                let shade = i * 28;
+                [shade, 100, 200 - shade, shade + 2, 101, 200 - shade]
+            })
+            .collect();
+        palette.extend([255, 0, 255]);
+        palette
+    }
+
+    fn testing_png(depth: u8, transparency: Option<Vec<u8>>) -> Png {
+        let mut bytes = Vec::new();
+        bytes.extend(WIDTH.to_be_bytes());
+        bytes.extend(HEIGHT.to_be_bytes());
+        bytes.extend([depth, ColorType::Indexed as u8, 0, 0, 0]);
+        let ihdr = Ihdr::try_from(&bytes[..]).unwrap();
+
+        // 17 colors do not fit in 4 bits; leave out the unpaired one there.
+        let mut palette = testing_palette();
+        palette.truncate(3 << depth.min(8));
+        let count = palette.len() as u32 / 3;
+        let mut chunks = vec![
+            ihdr.as_chunk(),
+            Chunk::new("PLTE".parse().unwrap(), palette),
+        ];
+        if let Some(alphas) = transparency {
+            chunks.push(Chunk::new("tRNS".parse().unwrap(), alphas));
+        }
+        chunks.push(Chunk::new("IEND".parse().unwrap(), Vec::new()));
+        let mut png = Png::from_chunks(chunks);
+
+        let indices = (0..WIDTH * HEIGHT).map(|i| (i * 7 % count) as u8).collect();
+        let data =
+            encoder::encode(&ihdr, &Samples::Eight(indices), &EncodeOptions::default()).unwrap();
+        png.replace_image_data(&data);
+        png
+    }
+
+    fn colors(png: &Png) -> Vec<[u8; 4]> {
+        decoder::decode(png).unwrap().to_rgba8().unwrap()
+    }
+
+    #[test]
+    fn test_pairs() {
+        let palette = testing_palette();
+        let colors: Vec<[u8; 4]> = palette
+            .chunks_exact(3)
+            .map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+            .collect();
+        let pairs = pairs(&colors);
+        assert_eq!(pairs[0], Some(Pair { partner: 1, bit: 0 }));
+        assert_eq!(pairs[1], Some(Pair { partner: 0, bit: 1 }));
+        assert_eq!(
+            pairs[14],
+            Some(Pair {
+                partner: 15,
+                bit: 0
+            })
+        );
+        assert_eq!(pairs[16], None);
+    }
+
+    #[test]
+    fn test_transparency_splits_pairs() {
+        let colors = [[10, 10, 10, 255], [10, 10, 12, 0], [10, 12, 10, 255]];
+        let pairs = pairs(&colors);
+        assert_eq!(pairs[0], Some(Pair { partner: 2, bit: 0 }));
+        assert_eq!(pairs[1], None);
+    }
+
+    #[test]
+    fn test_embed_extract() {
+        for depth in [8, 4] {
+            let mut png = testing_png(depth, None);
+            let before = colors(&png);
+            let message = b"This is where your secret message will be!";
+            embed(&mut png, message).unwrap();
+            assert_eq!(extract(&png).unwrap(), message);
+
+            let after = colors(&png);
+            let changed = before.iter().zip(&after).filter(|(a, b)| a != b).count();
+            assert!(changed > 0);
+            assert!(before
+                .iter()
+                .zip(&after)
+                .all(|(&a, &b)| distance(a, b) <= MAX_DISTANCE));
+        }
+    }
+
+    #[test]
+    fn test_far_colors_are_untouched() {
+        let mut png = testing_png(8, None);
+        let before = colors(&png);
+        let capacity = capacity(&png).unwrap();
+        embed(&mut png, &vec![0xa5; capacity]).unwrap();
+        let after = colors(&png);
+        for (a, b) in before.iter().zip(&after) {
+            if *a == [255, 0, 255, 255] {
+                assert_eq!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        let png = testing_png(8, None);
+        // One in 17 pixels uses the unpaired color.
+        let carriers = (0..WIDTH * HEIGHT).filter(|i| i * 7 % 17 != 16).count();
+        assert_eq!(capacity(&png).unwrap(), carriers / 8 - LENGTH_PREFIX);
+
+        let mut png = testing_png(8, None);
+        let too_large = vec![0; capacity(&png).unwrap() + 1];
+        assert!(matches!(
+            embed(&mut png, &too_large),
+            Err(Error::MessageTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_transparency() {
+        // Making every other color transparent breaks up all pairs.
+        let alphas = (0..17).map(|i| if i % 2 == 0 { 255 } else { 0 }).collect();
+        let png = testing_png(8, Some(alphas));
+        assert_eq!(capacity(&png).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_extract_without_message() {
+        let png = testing_png(8, None);
+        assert!(matches!(extract(&png), Err(Error::MessageNotFound)));
+    }
+
+    #[test]
+    fn test_truecolor_is_unsupported() {
+        let png = Png::try_from(&include_bytes!("../image/dice.png")[..]).unwrap();
+        assert!(matches!(capacity(&png), Err(Error::Unsupported(_))));
+    }
+}
@@ -0,0 +1,332 @@
+use crate::{compression::Compression, crypto, fragment, Error, Result};
+use std::path::Path;
+
+/// Marks payloads that carry a header produced by [`Payload::as_bytes`].
+pub const MAGIC: [u8; 4] = *b"StPl";
+pub const VERSION: u8 = 2;
+
+/// Header layout: magic (4) | version (1) | compression (1) | name length (2) |
+/// name | MIME type length (1) | MIME type. The data follows, compressed.
+/// Version 1 headers have no compression byte. Empty names and MIME types
+/// mean the payload is not a file.
+pub const MIN_HEADER_LENGTH: usize = MAGIC.len() + 1 + 1 + 2 + 1;
+
+/// Prefixes that `decode` reads as a header of its own. Plain messages that
+/// start with one are given a payload header so they are not mistaken for it.
+const RESERVED_PREFIXES: [[u8; 4]; 3] = [MAGIC, crypto::MAGIC, fragment::MAGIC];
+
+/// The bytes hidden in an image, along with the file they were read from, if
+/// any, so they can be restored under the same name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload {
+    file_name: Option<String>,
+    mime_type: Option<String>,
+    compression: Compression,
+    header_required: bool,
+    data: Vec<u8>,
+}
+
+impl Payload {
+    /// A plain message. It is stored as is, without a header, so images
+    /// carrying text stay readable by older versions, unless it starts like
+    /// one of the headers `decode` recognizes.
+    pub fn new(data: Vec<u8>) -> Payload {
+        Payload {
+            file_name: None,
+            mime_type: None,
+            compression: Compression::None,
+            header_required: false,
+            data,
+        }
+    }
+
+    /// The contents of a file. Only the final component of `file_name` is
+    /// kept, and the MIME type is guessed from its extension.
+    pub fn from_file(file_name: &str, data: Vec<u8>) -> Result<Payload> {
+        let file_name = sanitize_file_name(file_name)
+            .ok_or(Error::InvalidHeader("File name is empty."))?
+            .to_string();
+        if file_name.len() > u16::MAX as usize {
+            return Err(Error::InvalidHeader("File name is too long."));
+        }
+        Ok(Payload {
+            mime_type: Some(mime_type(&file_name).to_string()),
+            file_name: Some(file_name),
+            compression: Compression::None,
+            header_required: false,
+            data,
+        })
+    }
+
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    pub fn mime_type(&self) -> Option<&str> {
+        self.mime_type.as_deref()
+    }
+
+    /// How the data is compressed when stored.
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Whether the header is written even for a plain message, so the stored
+    /// bytes can be told apart from data that merely looks the same.
+    pub fn set_header_required(&mut self, header_required: bool) {
+        self.header_required = header_required;
+    }
+
+    /// The uncompressed data.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
+        self.encode(self.compression)
+    }
+
+    /// Like [`Payload::as_bytes`], but with whichever available compression,
+    /// including none, gives the fewest bytes.
+    pub fn as_smallest_bytes(&self) -> Result<Vec<u8>> {
+        let mut smallest = self.encode(Compression::None)?;
+        for compression in Compression::ALL
+            .into_iter()
+            .filter(Compression::is_available)
+        {
+            let bytes = self.encode(compression)?;
+            if bytes.len() < smallest.len() {
+                smallest = bytes;
+            }
+        }
+        Ok(smallest)
+    }
+
+    fn encode(&self, compression: Compression) -> Result<Vec<u8>> {
+        let reserved = RESERVED_PREFIXES
+            .iter()
+            .any(|prefix| self.data.starts_with(prefix));
+        let header = self.header_required || reserved || self.file_name.is_some();
+        if !header && compression == Compression::None {
+            return Ok(self.data.clone());
+        }
+
+        let file_name = self.file_name.as_deref().unwrap_or_default();
+        let mime_type = self.mime_type.as_deref().unwrap_or_default();
+        let data = compression.compress(&self.data)?;
+        let mut bytes =
+            Vec::with_capacity(MIN_HEADER_LENGTH + file_name.len() + mime_type.len() + data.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+        bytes.push(compression as u8);
+        bytes.extend((file_name.len() as u16).to_be_bytes());
+        bytes.extend(file_name.bytes());
+        bytes.push(mime_type.len() as u8);
+        bytes.extend(mime_type.bytes());
+        bytes.extend(data);
+        Ok(bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for Payload {
+    type Error = Error;
+
+    /// Data without a header is a plain message.
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        if !bytes.starts_with(&MAGIC) {
+            return Ok(Payload::new(bytes.to_vec()));
+        }
+        let truncated = Error::InvalidHeader("Header is truncated.");
+        let (version, rest) = match bytes[MAGIC.len()..].split_first() {
+            Some((&version, rest)) => (version, rest),
+            None => return Err(truncated),
+        };
+        let (compression, rest) = match (version, rest.split_first()) {
+            (1, _) => (Compression::None, rest),
+            (VERSION, Some((&compression, rest))) => (Compression::try_from(compression)?, rest),
+            (VERSION, None) => return Err(truncated),
+            _ => return Err(Error::InvalidHeader("Unsupported version.")),
+        };
+
+        let (file_name, rest) = match rest {
+            [a, b, ..] => take_field(rest, u16::from_be_bytes([*a, *b]), 2)?,
+            _ => return Err(truncated),
+        };
+        let (mime_type, rest) = match rest.first() {
+            Some(&length) => take_field(rest, length as u16, 1)?,
+            None => return Err(truncated),
+        };
+        let file_name = match file_name.is_empty() {
+            true => None,
+            false => Some(
+                sanitize_file_name(&file_name)
+                    .ok_or(Error::InvalidHeader("File name is empty."))?
+                    .to_string(),
+            ),
+        };
+
+        Ok(Payload {
+            file_name,
+            mime_type: Some(mime_type).filter(|mime_type| !mime_type.is_empty()),
+            compression,
+            header_required: false,
+            data: compression.decompress(rest)?,
+        })
+    }
+}
+
+/// Splits a UTF-8 field of `length` bytes, preceded by `skip` bytes holding
+/// that length, off the front of `bytes`.
+fn take_field(bytes: &[u8], length: u16, skip: usize) -> Result<(String, &[u8])> {
+    let end = skip + length as usize;
+    if bytes.len() < end {
+        return Err(Error::InvalidHeader("Header is truncated."));
+    }
+    let field = String::from_utf8(bytes[skip..end].to_vec())
+        .map_err(|_| Error::InvalidHeader("Field is not valid UTF-8."))?;
+    Ok((field, &bytes[end..]))
+}
+
+/// Keeps only the final component of a path, so a crafted payload cannot make
+/// `decode` write outside the chosen directory.
+fn sanitize_file_name(file_name: &str) -> Option<&str> {
+    Path::new(file_name)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .filter(|name| !name.is_empty())
+}
+
+/// Guesses the MIME type of a file from its extension.
+pub fn mime_type(file_name: &str) -> &'static str {
+    let extension = Path::new(file_name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("txt") => "text/plain",
+        Some("json") => "application/json",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("gz") => "application/gzip",
+        Some("tar") => "application/x-tar",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("pem") => "application/x-pem-file",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_message_has_no_header() {
+        let payload = Payload::new(b"hello".to_vec());
+        assert_eq!(payload.as_bytes().unwrap(), b"hello");
+        assert_eq!(Payload::try_from(&b"hello"[..]).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_plain_message_that_looks_like_a_header() {
+        for text in [&b"StEn is my name"[..], b"StPl here", b"StFr"] {
+            let payload = Payload::new(text.to_vec());
+            let bytes = payload.as_bytes().unwrap();
+            assert!(bytes.starts_with(&MAGIC));
+            assert!(!crypto::is_encrypted(&bytes));
+            assert_eq!(Payload::try_from(&bytes[..]).unwrap(), payload);
+            assert_eq!(payload.as_smallest_bytes().unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_header_required() {
+        let mut payload = Payload::new(b"hello".to_vec());
+        payload.set_header_required(true);
+        let bytes = payload.as_bytes().unwrap();
+        assert!(bytes.starts_with(&MAGIC));
+        assert_eq!(Payload::try_from(&bytes[..]).unwrap().data(), b"hello");
+    }
+
+    #[test]
+    fn test_file_roundtrip() {
+        let payload = Payload::from_file("keys/secret.zip", vec![0, 159, 146, 150]).unwrap();
+        assert_eq!(payload.file_name(), Some("secret.zip"));
+        assert_eq!(payload.mime_type(), Some("application/zip"));
+
+        let bytes = payload.as_bytes().unwrap();
+        assert!(bytes.starts_with(&MAGIC));
+        assert_eq!(Payload::try_from(&bytes[..]).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_file_name_is_sanitized() {
+        // Version 1 header, without compression.
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(1);
+        bytes.extend(13u16.to_be_bytes());
+        bytes.extend(b"../../.bashrc");
+        bytes.push(0);
+        let payload = Payload::try_from(&bytes[..]).unwrap();
+        assert_eq!(payload.file_name(), Some(".bashrc"));
+
+        assert!(Payload::from_file("..", Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_truncated_header() {
+        let bytes = Payload::from_file("notes.txt", b"notes".to_vec())
+            .unwrap()
+            .as_bytes()
+            .unwrap();
+        for end in MAGIC.len()..MIN_HEADER_LENGTH + "notes.txt".len() + "text/plain".len() {
+            assert!(matches!(
+                Payload::try_from(&bytes[..end]),
+                Err(Error::InvalidHeader(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_compressed_roundtrip() {
+        let text = b"the quick brown fox jumps over the lazy dog ".repeat(20);
+        let mut payload = Payload::new(text.clone());
+        payload.set_compression(Compression::Deflate);
+        let bytes = payload.as_bytes().unwrap();
+        assert!(bytes.starts_with(&MAGIC));
+        assert!(bytes.len() < text.len());
+
+        let parsed = Payload::try_from(&bytes[..]).unwrap();
+        assert_eq!(parsed.compression(), Compression::Deflate);
+        assert_eq!(parsed.file_name(), None);
+        assert_eq!(parsed.data(), text);
+    }
+
+    #[test]
+    fn test_smallest_bytes() {
+        let payload = Payload::new(b"short".to_vec());
+        assert_eq!(payload.as_smallest_bytes().unwrap(), b"short");
+
+        let text = b"the quick brown fox jumps over the lazy dog ".repeat(20);
+        let payload = Payload::new(text.clone());
+        let bytes = payload.as_smallest_bytes().unwrap();
+        assert!(bytes.len() < text.len());
+        assert_eq!(Payload::try_from(&bytes[..]).unwrap().data(), text);
+    }
+
+    #[test]
+    fn test_mime_type() {
+        assert_eq!(mime_type("photo.JPG"), "image/jpeg");
+        assert_eq!(mime_type("notes.txt"), "text/plain");
+        assert_eq!(mime_type("id_ed25519"), "application/octet-stream");
+    }
+}
@@ -1,5 +1,35 @@
-use crate::{chunk::Chunk, Error, Result};
-use std::{convert::TryFrom, io};
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    ihdr::Ihdr,
+    validate::{self, ValidationMode, Violation},
+    Error, Result,
+};
+use std::{convert::TryFrom, str::FromStr};
+
+/// Where a new chunk is inserted by [`Png::insert_chunk`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// Right before `IEND`, after all image data.
+    BeforeEnd,
+    /// Right before the first `IDAT`, after `IHDR` and any palette.
+    BeforeImageData,
+    /// After every other chunk, including `IEND`.
+    End,
+}
+
+impl Placement {
+    /// Returns true if a chunk placed this way goes right before the first
+    /// chunk of type `chunk_type`.
+    pub fn is_anchor(&self, chunk_type: &ChunkType) -> bool {
+        let anchors: &[&str] = match self {
+            Placement::BeforeEnd => &["IEND"],
+            Placement::BeforeImageData => &["IDAT", "IEND"],
+            Placement::End => &[],
+        };
+        anchors.contains(&chunk_type.to_string().as_str())
+    }
+}
 
 #[derive(Debug)]
 pub struct Png {
@@ -8,28 +38,121 @@ pub struct Png {
 
 impl Png {
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+    pub const IDAT_CHUNK_SIZE: usize = 8192;
 
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
         Png { chunks }
     }
 
+    /// Parses a PNG file. In strict mode, the file is rejected if its chunks
+    /// violate the ordering rules of the PNG specification; in lenient mode,
+    /// which `Png::try_from` uses, they are left for [`Png::validate`] to
+    /// report.
+    pub fn from_bytes(bytes: &[u8], mode: ValidationMode) -> Result<Png> {
+        if bytes.len() < 8 || bytes[..8] != Png::STANDARD_HEADER {
+            return Err(Error::InvalidSignature);
+        }
+
+        let mut i = 8;
+        let mut chunks = Vec::new();
+        while i < bytes.len() {
+            let truncated = Error::TruncatedChunk { offset: i as u64 };
+            let length = match bytes.get(i..i + 4) {
+                Some(&[a, b, c, d]) => u32::from_be_bytes([a, b, c, d]),
+                _ => return Err(truncated),
+            };
+            if length > Chunk::MAX_LENGTH {
+                return Err(Error::ChunkTooLong {
+                    length,
+                    offset: i as u64,
+                });
+            }
+            let chunk_bytes = (length as usize)
+                .checked_add(i + 12)
+                .and_then(|chunk_end| bytes.get(i..chunk_end))
+                .ok_or(truncated)?;
+            let chunk = Chunk::try_from(chunk_bytes).map_err(|e| e.offset_by(i as u64))?;
+            chunks.push(chunk);
+            i += chunk_bytes.len();
+        }
+
+        let png = Png { chunks };
+        if mode == ValidationMode::Strict {
+            let violations = png.validate();
+            if !violations.is_empty() {
+                return Err(Error::InvalidStructure(violations));
+            }
+        }
+        Ok(png)
+    }
+
     pub fn append_chunk(&mut self, chunk: Chunk) {
         self.chunks.push(chunk);
     }
 
-    pub fn remove_first_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
+    /// Inserts `chunk` right before the first chunk of type `chunk_type`.
+    pub fn insert_chunk_before(&mut self, chunk_type: &str, chunk: Chunk) -> Result<()> {
+        match self.position(chunk_type) {
+            Some(i) => {
+                self.chunks.insert(i, chunk);
+                Ok(())
+            }
+            None => Err(Error::ChunkNotFound(chunk_type.to_string())),
+        }
+    }
+
+    /// Inserts `chunk` right after the last chunk of type `chunk_type`.
+    pub fn insert_chunk_after(&mut self, chunk_type: &str, chunk: Chunk) -> Result<()> {
         let index = self
             .chunks
             .iter()
-            .position(|chunk| chunk.chunk_type().to_string() == chunk_type);
+            .rposition(|chunk| chunk.chunk_type().to_string() == chunk_type);
 
         match index {
+            Some(i) => {
+                self.chunks.insert(i + 1, chunk);
+                Ok(())
+            }
+            None => Err(Error::ChunkNotFound(chunk_type.to_string())),
+        }
+    }
+
+    /// Inserts `chunk` according to `placement`. If the image lacks the chunk
+    /// the placement refers to, the next best position is used, falling back
+    /// to appending the chunk.
+    pub fn insert_chunk(&mut self, chunk: Chunk, placement: Placement) {
+        let index = match placement {
+            Placement::BeforeEnd => self.position("IEND"),
+            Placement::BeforeImageData => self.position("IDAT").or(self.position("IEND")),
+            Placement::End => None,
+        };
+        self.chunks
+            .insert(index.unwrap_or(self.chunks.len()), chunk);
+    }
+
+    fn position(&self, chunk_type: &str) -> Option<usize> {
+        self.chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    pub fn remove_first_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
+        match self.position(chunk_type) {
             Some(i) => Ok(self.chunks.remove(i)),
-            None => Err(Box::new(io::Error::new(
-                io::ErrorKind::NotFound,
-                "Chunk not found",
-            ))),
+            None => Err(Error::ChunkNotFound(chunk_type.to_string())),
+        }
+    }
+
+    /// Removes every chunk of the given type, returning them in file order.
+    pub fn remove_all_chunks(&mut self, chunk_type: &str) -> Result<Vec<Chunk>> {
+        let (removed, kept) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|chunk| chunk.chunk_type().to_string() == chunk_type);
+        self.chunks = kept;
+        if removed.is_empty() {
+            return Err(Error::ChunkNotFound(chunk_type.to_string()));
         }
+        Ok(removed)
     }
 
     pub fn header(&self) -> &[u8; 8] {
@@ -40,15 +163,86 @@ impl Png {
         &self.chunks
     }
 
+    /// Parses the image header, which must be the first chunk.
+    pub fn ihdr(&self) -> Result<Ihdr> {
+        match self.chunks.first() {
+            Some(chunk) => Ihdr::try_from(chunk),
+            None => Err(Error::InvalidIhdr("Missing IHDR chunk.")),
+        }
+    }
+
+    /// Checks the chunks against the ordering rules of the PNG specification.
+    pub fn validate(&self) -> Vec<Violation> {
+        validate::validate(&self.chunks)
+    }
+
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
         self.chunks
             .iter()
             .find(|chunk| chunk.chunk_type().to_string() == chunk_type)
     }
 
+    /// All chunks of the given type, in file order.
+    pub fn chunks_by_type<'a>(&'a self, chunk_type: &'a str) -> impl Iterator<Item = &'a Chunk> {
+        self.chunks
+            .iter()
+            .filter(move |chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    /// A covert chunk type that no chunk in the image uses yet. See
+    /// [`ChunkType::covert`].
+    pub fn unused_chunk_type(&self, mimic: bool) -> ChunkType {
+        ChunkType::covert(mimic, |chunk_type| {
+            self.chunks
+                .iter()
+                .any(|chunk| chunk.chunk_type() == chunk_type)
+        })
+    }
+
+    /// The data of all `IDAT` chunks, concatenated.
+    pub fn image_data(&self) -> Vec<u8> {
+        self.chunks_by_type("IDAT")
+            .flat_map(|chunk| chunk.data().iter().copied())
+            .collect()
+    }
+
+    /// Replaces all `IDAT` chunks with `data`, split into chunks no longer than
+    /// the longest original `IDAT`. The new chunks take the place of the first
+    /// original `IDAT`, or go before `IEND` if there was none.
+    pub fn replace_image_data(&mut self, data: &[u8]) {
+        let chunk_size = self
+            .chunks_by_type("IDAT")
+            .map(|chunk| chunk.length() as usize)
+            .max()
+            .unwrap_or(Png::IDAT_CHUNK_SIZE);
+        self.replace_image_data_split(data, chunk_size);
+    }
+
+    /// Like [`Png::replace_image_data`], with `IDAT` chunks of at most
+    /// `chunk_size` bytes.
+    pub fn replace_image_data_split(&mut self, data: &[u8], chunk_size: usize) {
+        let is_image_data = |chunk: &Chunk| chunk.chunk_type().to_string() == "IDAT";
+        let index = self
+            .chunks
+            .iter()
+            .position(is_image_data)
+            .or_else(|| {
+                self.chunks
+                    .iter()
+                    .position(|chunk| chunk.chunk_type().to_string() == "IEND")
+            })
+            .unwrap_or(self.chunks.len());
+
+        self.chunks.retain(|chunk| !is_image_data(chunk));
+        let image_data = data
+            .chunks(chunk_size.max(1))
+            .map(|data| Chunk::new(ChunkType::from_str("IDAT").unwrap(), data.to_vec()));
+        self.chunks.splice(index..index, image_data);
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
-        bytes.extend_from_slice(&Png::STANDARD_HEADER);
+        bytes.extend_from_slice(self.header());
         for chunk in self.chunks() {
             bytes.extend(chunk.as_bytes());
         }
@@ -56,41 +250,15 @@ impl Png {
     }
 }
 
+/// Parses a PNG file in [`ValidationMode::Lenient`], accepting any sequence of
+/// well-formed chunks. Strict mode would reject files this crate writes on
+/// purpose, such as ones with chunks after `IEND`; use [`Png::from_bytes`] to
+/// reject misordered files, or [`Png::validate`] to list the problems.
 impl TryFrom<&[u8]> for Png {
     type Error = Error;
 
     fn try_from(value: &[u8]) -> Result<Self> {
-        if value.len() < 8 {
-            return Err(Box::new(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Invalid PNG file. Not enough bytes.",
-            )));
-        }
-
-        if value[..8] != Png::STANDARD_HEADER {
-            return Err(Box::new(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Invalid PNG file. Incorrect header.",
-            )));
-        }
-
-        let mut i = 8;
-        let mut chunks = Vec::new();
-        while i < value.len() {
-            let length = u32::from_be_bytes(value[i..i + 4].try_into().unwrap());
-            let chunk_end = i + 4 + 4 + length as usize + 4;
-            let chunk = Chunk::try_from(&value[i..chunk_end])?;
-            chunks.push(chunk);
-            i = chunk_end;
-        }
-        if i != value.len() {
-            return Err(Box::new(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Invalid PNG file. Incorrect chunk length.",
-            )));
-        }
-
-        Ok(Png { chunks })
+        Png::from_bytes(value, ValidationMode::Lenient)
     }
 }
 
@@ -108,8 +276,8 @@ mod tests {
     use super::*;
     use crate::chunk::Chunk;
     use crate::chunk_type::ChunkType;
+    use crate::ihdr::ColorType;
     use std::convert::TryFrom;
-    use std::str::FromStr;
 
     fn testing_chunks() -> Vec<Chunk> {
         vec![
@@ -174,7 +342,7 @@ mod tests {
 
         let png = Png::try_from(bytes.as_ref());
 
-        assert!(png.is_err());
+        assert!(matches!(png, Err(Error::InvalidSignature)));
     }
 
     #[test]
@@ -214,6 +382,18 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
     }
 
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("FrSt", "I am another chunk").unwrap());
+        let data: Vec<String> = png
+            .chunks_by_type("FrSt")
+            .map(|chunk| chunk.data_as_string().unwrap())
+            .collect();
+        assert_eq!(data, ["I am the first chunk", "I am another chunk"]);
+        assert_eq!(png.chunks_by_type("IDAT").count(), 0);
+    }
+
     #[test]
     fn test_append_chunk() {
         let mut png = testing_png();
@@ -223,6 +403,63 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    #[test]
+    fn test_insert_chunk_before() {
+        let mut png = testing_png();
+        png.insert_chunk_before("miDl", chunk_from_strings("TeSt", "Message").unwrap())
+            .unwrap();
+        assert_eq!(&png.chunks()[1].chunk_type().to_string(), "TeSt");
+        assert!(png
+            .insert_chunk_before("NoPe", chunk_from_strings("TeSt", "Message").unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_insert_chunk_after() {
+        let mut png = testing_png();
+        png.insert_chunk_before("LASt", chunk_from_strings("miDl", "Again").unwrap())
+            .unwrap();
+        png.insert_chunk_after("miDl", chunk_from_strings("TeSt", "Message").unwrap())
+            .unwrap();
+        assert_eq!(&png.chunks()[3].chunk_type().to_string(), "TeSt");
+        assert!(png
+            .insert_chunk_after("NoPe", chunk_from_strings("TeSt", "Message").unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_insert_chunk_placement() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.insert_chunk(
+            chunk_from_strings("enDs", "Message").unwrap(),
+            Placement::BeforeEnd,
+        );
+        png.insert_chunk(
+            chunk_from_strings("daTa", "Message").unwrap(),
+            Placement::BeforeImageData,
+        );
+        png.insert_chunk(
+            chunk_from_strings("laSt", "Message").unwrap(),
+            Placement::End,
+        );
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(
+            types,
+            ["IHDR", "sRGB", "gAMA", "pHYs", "daTa", "IDAT", "RuSt", "enDs", "IEND", "laSt"]
+        );
+
+        let mut png = testing_png();
+        png.insert_chunk(
+            chunk_from_strings("TeSt", "Message").unwrap(),
+            Placement::BeforeImageData,
+        );
+        assert_eq!(&png.chunks()[3].chunk_type().to_string(), "TeSt");
+    }
+
     #[test]
     fn test_remove_first_chunk() {
         let mut png = testing_png();
@@ -232,6 +469,84 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_remove_all_chunks() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "First").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "Second").unwrap());
+        let removed = png.remove_all_chunks("TeSt").unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(removed[1].data_as_string().unwrap(), "Second");
+        assert_eq!(png.chunks().len(), 3);
+        assert!(png.remove_all_chunks("TeSt").is_err());
+    }
+
+    #[test]
+    fn test_unused_chunk_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("npTc", "Taken").unwrap());
+        assert_eq!(png.unused_chunk_type(true).to_string(), "npLb");
+        let chunk_type = png.unused_chunk_type(false);
+        assert!(png.chunk_by_type(&chunk_type.to_string()).is_none());
+    }
+
+    #[test]
+    fn test_ihdr() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let ihdr = png.ihdr().unwrap();
+        assert_eq!(ihdr.width(), 50);
+        assert_eq!(ihdr.height(), 50);
+        assert_eq!(ihdr.bit_depth(), 8);
+        assert_eq!(ihdr.color_type(), ColorType::TruecolorAlpha);
+
+        assert!(testing_png().ihdr().is_err());
+        assert!(Png::from_chunks(Vec::new()).ihdr().is_err());
+    }
+
+    #[test]
+    fn test_image_data() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.image_data(), png.chunk_by_type("IDAT").unwrap().data());
+        assert!(testing_png().image_data().is_empty());
+    }
+
+    #[test]
+    fn test_replace_image_data() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.replace_image_data(&[7; 10000]);
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(
+            types,
+            ["IHDR", "sRGB", "gAMA", "pHYs", "IDAT", "IDAT", "IDAT", "RuSt", "IEND"]
+        );
+        assert_eq!(png.chunks()[4].length(), 4681);
+        assert_eq!(png.chunks()[6].length(), 10000 - 2 * 4681);
+    }
+
+    #[test]
+    fn test_from_bytes_strict() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.remove_first_chunk("RuSt").unwrap();
+        let bytes = png.as_bytes();
+        assert!(png.validate().is_empty());
+        assert!(Png::from_bytes(&bytes, ValidationMode::Strict).is_ok());
+
+        png.append_chunk(chunk_from_strings("ruSt", "Message").unwrap());
+        let bytes = png.as_bytes();
+        assert!(Png::from_bytes(&bytes, ValidationMode::Lenient).is_ok());
+        assert!(Png::from_bytes(&bytes, ValidationMode::Strict).is_err());
+        assert_eq!(png.validate().len(), 2);
+
+        // Plain parsing is the lenient pass.
+        let lenient = Png::try_from(&bytes[..]).unwrap();
+        assert_eq!(lenient.as_bytes(), bytes);
+        assert_eq!(lenient.validate(), png.validate());
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -264,6 +579,67 @@ mod tests {
         let _png_string = format!("{}", png);
     }
 
+    #[test]
+    fn test_truncated_input() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let boundaries: Vec<usize> = png
+            .chunks()
+            .iter()
+            .scan(Png::STANDARD_HEADER.len(), |end, chunk| {
+                *end += chunk.as_bytes().len();
+                Some(*end)
+            })
+            .collect();
+
+        for end in 0..PNG_FILE.len() {
+            let png = Png::try_from(&PNG_FILE[..end]);
+            assert_eq!(png.is_ok(), end == 8 || boundaries.contains(&end));
+        }
+    }
+
+    #[test]
+    fn test_declared_length_too_large() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            Png::try_from(bytes.as_ref()),
+            Err(Error::ChunkTooLong { offset: 8, .. })
+        ));
+
+        bytes[8..12].copy_from_slice(&Chunk::MAX_LENGTH.to_be_bytes());
+        assert!(matches!(
+            Png::try_from(bytes.as_ref()),
+            Err(Error::TruncatedChunk { offset: 8 })
+        ));
+    }
+
+    #[test]
+    fn test_mutated_input() {
+        // Deterministic xorshift, so failures are reproducible.
+        let mut state: u32 = 0x9e37_79b9;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as usize
+        };
+
+        for _ in 0..5000 {
+            let mut bytes = PNG_FILE.to_vec();
+            for _ in 0..1 + next() % 4 {
+                let index = next() % bytes.len();
+                bytes[index] = next() as u8;
+            }
+            bytes.truncate(next() % (bytes.len() + 1));
+
+            if let Ok(png) = Png::try_from(bytes.as_ref()) {
+                let _ = png.ihdr();
+                let _ = png.validate();
+                let _ = png.to_string();
+            }
+        }
+    }
+
     // This is the raw bytes for a shrunken version of the `dice.png` image on Wikipedia
     const PNG_FILE: [u8; 4803] = [
         137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 50, 0, 0, 0, 50, 8,
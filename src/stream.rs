@@ -0,0 +1,218 @@
+use crate::{chunk::Chunk, chunk_type::ChunkType, png::Png, Error, Result};
+use std::io::{self, Read, Write};
+
+/// Reads the chunks of a PNG file one at a time, after checking the signature.
+pub struct ChunkReader<R: Read> {
+    reader: R,
+    offset: u64,
+    done: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    pub fn new(mut reader: R) -> Result<ChunkReader<R>> {
+        let mut header = [0; 8];
+        if read_fully(&mut reader, &mut header)? < header.len() || header != Png::STANDARD_HEADER {
+            return Err(Error::InvalidSignature);
+        }
+
+        Ok(ChunkReader {
+            reader,
+            offset: header.len() as u64,
+            done: false,
+        })
+    }
+
+    fn read_chunk(&mut self) -> Result<Option<Chunk>> {
+        let mut length = [0; 4];
+        let read = read_fully(&mut self.reader, &mut length)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        let truncated = Error::TruncatedChunk {
+            offset: self.offset,
+        };
+        if read < length.len() {
+            return Err(truncated);
+        }
+        let length = u32::from_be_bytes(length);
+        if length > Chunk::MAX_LENGTH {
+            return Err(Error::ChunkTooLong {
+                length,
+                offset: self.offset,
+            });
+        }
+
+        let mut chunk_type = [0; 4];
+        if read_fully(&mut self.reader, &mut chunk_type)? < chunk_type.len() {
+            return Err(truncated);
+        }
+        let chunk_type = ChunkType::try_from(chunk_type)?;
+
+        // Grow the buffer as data arrives instead of trusting the declared length.
+        let mut data = Vec::new();
+        (&mut self.reader)
+            .take(length as u64)
+            .read_to_end(&mut data)?;
+        if data.len() < length as usize {
+            return Err(truncated);
+        }
+
+        let mut crc = [0; 4];
+        if read_fully(&mut self.reader, &mut crc)? < crc.len() {
+            return Err(truncated);
+        }
+
+        let chunk = Chunk::new(chunk_type, data);
+        if chunk.crc() != u32::from_be_bytes(crc) {
+            return Err(Error::CrcMismatch {
+                chunk_type: chunk.chunk_type().clone(),
+                expected: chunk.crc(),
+                actual: u32::from_be_bytes(crc),
+                offset: self.offset,
+            });
+        }
+        self.offset += 12 + length as u64;
+        Ok(Some(chunk))
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let chunk = self.read_chunk().transpose();
+        if !matches!(chunk, Some(Ok(_))) {
+            self.done = true;
+        }
+        chunk
+    }
+}
+
+/// Writes the signature and then chunks of a PNG file as they are produced.
+pub struct ChunkWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    pub fn new(mut writer: W) -> Result<ChunkWriter<W>> {
+        writer.write_all(&Png::STANDARD_HEADER)?;
+        Ok(ChunkWriter { writer })
+    }
+
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+        self.writer.write_all(&chunk.length().to_be_bytes())?;
+        self.writer.write_all(&chunk.chunk_type().bytes())?;
+        self.writer.write_all(chunk.data())?;
+        self.writer.write_all(&chunk.crc().to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Flushes the writer and returns it.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Like `Read::read_exact`, but returns how many bytes were read instead of
+/// failing when the reader runs out.
+fn read_fully<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(Error::Io(e)),
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG_FILE: &[u8] = include_bytes!("../image/dice.png");
+
+    #[test]
+    fn test_read_chunks() {
+        let chunks: Vec<Chunk> = ChunkReader::new(PNG_FILE)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        let png = Png::try_from(PNG_FILE).unwrap();
+        assert_eq!(chunks.len(), png.chunks().len());
+        for (chunk, expected) in chunks.iter().zip(png.chunks()) {
+            assert_eq!(chunk.chunk_type(), expected.chunk_type());
+            assert_eq!(chunk.data(), expected.data());
+            assert_eq!(chunk.crc(), expected.crc());
+        }
+    }
+
+    #[test]
+    fn test_write_chunks() {
+        let mut writer = ChunkWriter::new(Vec::new()).unwrap();
+        for chunk in ChunkReader::new(PNG_FILE).unwrap() {
+            writer.write_chunk(&chunk.unwrap()).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), PNG_FILE);
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        assert!(matches!(
+            ChunkReader::new(&[13, 80, 78, 71, 13, 10, 26, 10][..]),
+            Err(Error::InvalidSignature)
+        ));
+        assert!(matches!(
+            ChunkReader::new(&PNG_FILE[..4]),
+            Err(Error::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_truncated_chunk() {
+        for end in [10, 20, 34, 100, PNG_FILE.len() - 1] {
+            let chunks: Vec<Result<Chunk>> = ChunkReader::new(&PNG_FILE[..end]).unwrap().collect();
+            assert!(chunks.last().unwrap().is_err());
+        }
+    }
+
+    #[test]
+    fn test_invalid_crc() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[20] ^= 1;
+        let mut reader = ChunkReader::new(&bytes[..]).unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(Error::CrcMismatch { offset: 8, .. }))
+        ));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_declared_length_too_large() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        let mut reader = ChunkReader::new(&bytes[..]).unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(Error::ChunkTooLong { offset: 8, .. }))
+        ));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_truncated_input() {
+        for end in 0..PNG_FILE.len() {
+            if let Ok(reader) = ChunkReader::new(&PNG_FILE[..end]) {
+                let chunks: Vec<Result<Chunk>> = reader.collect();
+                assert!(chunks.iter().filter(|chunk| chunk.is_err()).count() <= 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,494 @@
+use crate::{chunk::Chunk, chunk_type::ChunkType, crypto, fragment, payload, Error, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::{
+    fmt,
+    io::{Read, Write},
+    str::FromStr,
+};
+
+const MAX_KEYWORD_LENGTH: usize = 79;
+
+/// Longest text a compressed text chunk may expand to. Without a limit, a
+/// small crafted chunk could inflate until memory runs out.
+const MAX_TEXT_LENGTH: usize = 1 << 28;
+
+/// A `tEXt` chunk: a Latin-1 keyword and text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Text {
+    keyword: String,
+    text: String,
+}
+
+impl Text {
+    pub fn new(keyword: &str, text: &str) -> Result<Text> {
+        check_keyword(keyword)?;
+        check_latin1_text(text)?;
+        Ok(Text {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+        })
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn as_chunk(&self) -> Chunk {
+        let mut data = to_latin1(&self.keyword);
+        data.push(0);
+        data.extend(to_latin1(&self.text));
+        Chunk::new(ChunkType::from_str("tEXt").unwrap(), data)
+    }
+}
+
+impl TryFrom<&Chunk> for Text {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        check_type(chunk, "tEXt")?;
+        let (keyword, text) = split_keyword(chunk.data())?;
+        Text::new(&keyword, &from_latin1(text))
+    }
+}
+
+/// A `zTXt` chunk: a Latin-1 keyword and zlib compressed Latin-1 text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressedText {
+    keyword: String,
+    text: String,
+}
+
+impl CompressedText {
+    pub fn new(keyword: &str, text: &str) -> Result<CompressedText> {
+        check_keyword(keyword)?;
+        check_latin1_text(text)?;
+        Ok(CompressedText {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+        })
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn as_chunk(&self) -> Result<Chunk> {
+        let mut data = to_latin1(&self.keyword);
+        data.extend([0, 0]);
+        data.extend(compress(&to_latin1(&self.text))?);
+        Ok(Chunk::new(ChunkType::from_str("zTXt").unwrap(), data))
+    }
+}
+
+impl TryFrom<&Chunk> for CompressedText {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        check_type(chunk, "zTXt")?;
+        let (keyword, rest) = split_keyword(chunk.data())?;
+        match rest.split_first() {
+            Some((0, compressed)) => {
+                let text = decompress(compressed, MAX_TEXT_LENGTH)?;
+                CompressedText::new(&keyword, &from_latin1(&text))
+            }
+            Some(_) => Err(Error::InvalidText("Unknown compression method.")),
+            None => Err(Error::InvalidText("Compression method is missing.")),
+        }
+    }
+}
+
+/// An `iTXt` chunk: a Latin-1 keyword, a language tag, the keyword translated
+/// to that language and UTF-8 text, optionally compressed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternationalText {
+    keyword: String,
+    language_tag: String,
+    translated_keyword: String,
+    text: String,
+    compressed: bool,
+}
+
+impl InternationalText {
+    pub fn new(
+        keyword: &str,
+        language_tag: &str,
+        translated_keyword: &str,
+        text: &str,
+        compressed: bool,
+    ) -> Result<InternationalText> {
+        check_keyword(keyword)?;
+        if !language_tag
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+        {
+            return Err(Error::InvalidText(
+                "Language tag must be ASCII letters, digits and hyphens.",
+            ));
+        }
+        if translated_keyword.contains('\0') || text.contains('\0') {
+            return Err(Error::InvalidText("Text must not contain null characters."));
+        }
+        Ok(InternationalText {
+            keyword: keyword.to_string(),
+            language_tag: language_tag.to_string(),
+            translated_keyword: translated_keyword.to_string(),
+            text: text.to_string(),
+            compressed,
+        })
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn language_tag(&self) -> &str {
+        &self.language_tag
+    }
+
+    pub fn translated_keyword(&self) -> &str {
+        &self.translated_keyword
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    pub fn as_chunk(&self) -> Result<Chunk> {
+        let mut data = to_latin1(&self.keyword);
+        data.extend([0, self.compressed as u8, 0]);
+        data.extend(self.language_tag.bytes());
+        data.push(0);
+        data.extend(self.translated_keyword.bytes());
+        data.push(0);
+        match self.compressed {
+            true => data.extend(compress(self.text.as_bytes())?),
+            false => data.extend(self.text.bytes()),
+        }
+        Ok(Chunk::new(ChunkType::from_str("iTXt").unwrap(), data))
+    }
+}
+
+impl TryFrom<&Chunk> for InternationalText {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        check_type(chunk, "iTXt")?;
+        let (keyword, rest) = split_keyword(chunk.data())?;
+        let (compressed, rest) = match rest {
+            [0, 0, rest @ ..] => (false, rest),
+            [1, 0, rest @ ..] => (true, rest),
+            [0 | 1, _, ..] => return Err(Error::InvalidText("Unknown compression method.")),
+            [_, _, ..] => return Err(Error::InvalidText("Invalid compression flag.")),
+            _ => return Err(Error::InvalidText("Compression flag is missing.")),
+        };
+        let mut fields = rest.splitn(3, |&byte| byte == 0);
+        let (Some(language_tag), Some(translated_keyword), Some(text)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(Error::InvalidText(
+                "Language tag or translated keyword is missing.",
+            ));
+        };
+        let text = match compressed {
+            true => decompress(text, MAX_TEXT_LENGTH)?,
+            false => text.to_vec(),
+        };
+
+        let utf8 = |bytes: Vec<u8>| {
+            String::from_utf8(bytes).map_err(|_| Error::InvalidText("Text is not valid UTF-8."))
+        };
+        InternationalText::new(
+            &keyword,
+            &utf8(language_tag.to_vec())?,
+            &utf8(translated_keyword.to_vec())?,
+            &utf8(text)?,
+            compressed,
+        )
+    }
+}
+
+/// Any of the three standard text chunks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextChunk {
+    Text(Text),
+    Compressed(CompressedText),
+    International(InternationalText),
+}
+
+impl TextChunk {
+    pub fn keyword(&self) -> &str {
+        match self {
+            TextChunk::Text(text) => text.keyword(),
+            TextChunk::Compressed(text) => text.keyword(),
+            TextChunk::International(text) => text.keyword(),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        match self {
+            TextChunk::Text(text) => text.text(),
+            TextChunk::Compressed(text) => text.text(),
+            TextChunk::International(text) => text.text(),
+        }
+    }
+
+    pub fn as_chunk(&self) -> Result<Chunk> {
+        match self {
+            TextChunk::Text(text) => Ok(text.as_chunk()),
+            TextChunk::Compressed(text) => text.as_chunk(),
+            TextChunk::International(text) => text.as_chunk(),
+        }
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        match chunk.chunk_type().to_string().as_str() {
+            "tEXt" => Ok(TextChunk::Text(Text::try_from(chunk)?)),
+            "zTXt" => Ok(TextChunk::Compressed(CompressedText::try_from(chunk)?)),
+            "iTXt" => Ok(TextChunk::International(InternationalText::try_from(
+                chunk,
+            )?)),
+            _ => Err(Error::InvalidText("Not a text chunk.")),
+        }
+    }
+}
+
+impl fmt::Display for TextChunk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.keyword(), self.text())
+    }
+}
+
+/// Turns a payload into text for a text chunk. Payloads that are already text
+/// are kept as is; encrypted, compressed, file and fragment payloads are
+/// Base64 encoded. Other binary data cannot be told apart from Base64 text
+/// when read back, so it is rejected.
+pub fn payload_to_text(data: &[u8]) -> Result<String> {
+    if has_magic(data) {
+        return Ok(STANDARD.encode(data));
+    }
+    match std::str::from_utf8(data) {
+        Ok(text) if !text.contains('\0') => Ok(text.to_string()),
+        _ => Err(Error::InvalidText(
+            "Binary messages are only stored as text when compressed, encrypted or read from a file.",
+        )),
+    }
+}
+
+/// Reverses [`payload_to_text`].
+pub fn text_to_payload(text: &str) -> Vec<u8> {
+    match STANDARD.decode(text) {
+        Ok(data) if has_magic(&data) => data,
+        _ => text.as_bytes().to_vec(),
+    }
+}
+
+/// Returns true if `data` starts with the header of a payload, an encrypted
+/// payload or a fragment, which every message stored as text has.
+pub fn has_magic(data: &[u8]) -> bool {
+    [payload::MAGIC, crypto::MAGIC, fragment::MAGIC]
+        .iter()
+        .any(|magic| data.starts_with(magic))
+}
+
+fn check_type(chunk: &Chunk, chunk_type: &str) -> Result<()> {
+    if chunk.chunk_type().to_string() != chunk_type {
+        return Err(Error::InvalidText("Wrong chunk type."));
+    }
+    Ok(())
+}
+
+/// Keywords are 1 to 79 printable Latin-1 characters, without leading,
+/// trailing or consecutive spaces.
+fn check_keyword(keyword: &str) -> Result<()> {
+    if keyword.is_empty() || keyword.chars().count() > MAX_KEYWORD_LENGTH {
+        return Err(Error::InvalidText("Keyword must be 1 to 79 characters."));
+    }
+    if !keyword
+        .chars()
+        .all(|c| matches!(c as u32, 32..=126 | 161..=255))
+    {
+        return Err(Error::InvalidText("Keyword must be printable Latin-1."));
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return Err(Error::InvalidText(
+            "Keyword must not have leading, trailing or consecutive spaces.",
+        ));
+    }
+    Ok(())
+}
+
+fn check_latin1_text(text: &str) -> Result<()> {
+    if !text.chars().all(|c| c != '\0' && (c as u32) <= 255) {
+        return Err(Error::InvalidText(
+            "Text must be Latin-1 without null characters.",
+        ));
+    }
+    Ok(())
+}
+
+/// Splits the null terminated Latin-1 keyword off the front of chunk data.
+fn split_keyword(data: &[u8]) -> Result<(String, &[u8])> {
+    match data.iter().position(|&byte| byte == 0) {
+        Some(end) => Ok((from_latin1(&data[..end]), &data[end + 1..])),
+        None => Err(Error::InvalidText("Keyword is not null terminated.")),
+    }
+}
+
+fn to_latin1(text: &str) -> Vec<u8> {
+    text.chars().map(|c| c as u8).collect()
+}
+
+fn from_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+/// Inflates at most `limit` bytes of text.
+fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    ZlibDecoder::new(data)
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|_| Error::InvalidText("Compressed text is corrupt."))?;
+    if decompressed.len() > limit {
+        return Err(Error::InvalidText("Compressed text is too long."));
+    }
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_roundtrip() {
+        let text = Text::new("Comment", "Caf\u{e9} au lait").unwrap();
+        let chunk = text.as_chunk();
+        assert_eq!(chunk.chunk_type().to_string(), "tEXt");
+        assert_eq!(chunk.data(), b"Comment\0Caf\xe9 au lait");
+        assert_eq!(Text::try_from(&chunk).unwrap(), text);
+    }
+
+    #[test]
+    fn test_compressed_text_roundtrip() {
+        let text = CompressedText::new("Description", &"long text ".repeat(100)).unwrap();
+        let chunk = text.as_chunk().unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "zTXt");
+        assert!(chunk.data().len() < 200);
+        assert_eq!(CompressedText::try_from(&chunk).unwrap(), text);
+    }
+
+    #[test]
+    fn test_international_text_roundtrip() {
+        for compressed in [false, true] {
+            let text =
+                InternationalText::new("Title", "ja", "タイトル", "日本語のテキスト", compressed)
+                    .unwrap();
+            let chunk = text.as_chunk().unwrap();
+            assert_eq!(chunk.chunk_type().to_string(), "iTXt");
+            assert_eq!(InternationalText::try_from(&chunk).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn test_decompression_limit() {
+        let text = CompressedText::new("Comment", &"x".repeat(1000)).unwrap();
+        let chunk = text.as_chunk().unwrap();
+        let compressed = &chunk.data()["Comment".len() + 2..];
+        assert_eq!(decompress(compressed, 1000).unwrap().len(), 1000);
+        assert!(matches!(
+            decompress(compressed, 999),
+            Err(Error::InvalidText(_))
+        ));
+    }
+
+    #[test]
+    fn test_text_chunk() {
+        let chunk = Text::new("Author", "Ferris").unwrap().as_chunk();
+        let text = TextChunk::try_from(&chunk).unwrap();
+        assert_eq!(text.keyword(), "Author");
+        assert_eq!(text.to_string(), "Author: Ferris");
+        assert_eq!(text.as_chunk().unwrap().data(), chunk.data());
+
+        let chunk = Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            b"Author\0Ferris".to_vec(),
+        );
+        assert!(TextChunk::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_invalid_keyword() {
+        for keyword in [
+            "",
+            " Comment",
+            "Comment ",
+            "Two  spaces",
+            "Tab\there",
+            &"k".repeat(80),
+        ] {
+            assert!(Text::new(keyword, "text").is_err(), "{:?}", keyword);
+        }
+        assert!(Text::new(&"k".repeat(79), "text").is_ok());
+    }
+
+    #[test]
+    fn test_invalid_text() {
+        assert!(Text::new("Comment", "日本").is_err());
+        assert!(Text::new("Comment", "null\0").is_err());
+        assert!(InternationalText::new("Comment", "en us", "", "", false).is_err());
+
+        let chunk = Chunk::new(
+            ChunkType::from_str("tEXt").unwrap(),
+            b"no terminator".to_vec(),
+        );
+        assert!(Text::try_from(&chunk).is_err());
+        let chunk = Chunk::new(
+            ChunkType::from_str("zTXt").unwrap(),
+            b"Comment\0\0junk".to_vec(),
+        );
+        assert!(CompressedText::try_from(&chunk).is_err());
+        let chunk = Chunk::new(
+            ChunkType::from_str("iTXt").unwrap(),
+            b"Comment\0\0\0en".to_vec(),
+        );
+        assert!(InternationalText::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_payload_text() {
+        assert_eq!(payload_to_text(b"hello").unwrap(), "hello");
+        assert_eq!(text_to_payload("hello"), b"hello");
+
+        let encrypted = crypto::encrypt(b"password", b"hello").unwrap();
+        let text = payload_to_text(&encrypted).unwrap();
+        assert!(text.is_ascii());
+        assert_eq!(text_to_payload(&text), encrypted);
+
+        // Valid Base64 that does not decode to a known payload stays as is.
+        assert_eq!(text_to_payload("test"), b"test");
+        assert!(payload_to_text(&[0, 159, 146, 150]).is_err());
+    }
+}
@@ -0,0 +1,393 @@
+use crate::{chunk::Chunk, chunk_type::ChunkType, ihdr::ColorType, ihdr::Ihdr};
+use std::{collections::HashSet, fmt, str::FromStr};
+
+/// How structural problems found while parsing a PNG file are treated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationMode {
+    /// Any violation of the chunk ordering rules is an error.
+    Strict,
+    /// Violations are only reported by [`crate::png::Png::validate`].
+    Lenient,
+}
+
+/// Ancillary chunks that must appear before `PLTE` and `IDAT`.
+const BEFORE_PLTE: [&str; 5] = ["cHRM", "gAMA", "iCCP", "sBIT", "sRGB"];
+/// Ancillary chunks that must appear after `PLTE` and before `IDAT`.
+const AFTER_PLTE: [&str; 3] = ["bKGD", "hIST", "tRNS"];
+/// Ancillary chunks that must appear before `IDAT`.
+const BEFORE_IDAT: [&str; 3] = ["pHYs", "sPLT", "eXIf"];
+/// Chunks that may appear at most once.
+const UNIQUE: [&str; 14] = [
+    "IHDR", "PLTE", "IEND", "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "bKGD", "hIST", "tRNS", "pHYs",
+    "tIME", "eXIf",
+];
+const CRITICAL: [&str; 4] = ["IHDR", "PLTE", "IDAT", "IEND"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViolationKind {
+    MissingChunk,
+    NotFirst,
+    NotLast,
+    AfterEnd,
+    Duplicate,
+    NotContiguous,
+    MisplacedBefore(&'static str),
+    MisplacedAfter(&'static str),
+    UnexpectedPalette,
+    UnknownCritical,
+    ReservedBit,
+    Conflicting(&'static str),
+}
+
+/// A single violation of the chunk ordering rules of the PNG specification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    index: usize,
+    chunk_type: ChunkType,
+    kind: ViolationKind,
+}
+
+impl Violation {
+    /// Index of the offending chunk. For missing chunks, this is the index the
+    /// chunk was expected at.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    pub fn kind(&self) -> &ViolationKind {
+        &self.kind
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "chunk {} ({}): ", self.index, self.chunk_type)?;
+        match &self.kind {
+            ViolationKind::MissingChunk => write!(f, "required chunk is missing"),
+            ViolationKind::NotFirst => write!(f, "must be the first chunk"),
+            ViolationKind::NotLast => write!(f, "must be the last chunk"),
+            ViolationKind::AfterEnd => write!(f, "appears after IEND"),
+            ViolationKind::Duplicate => write!(f, "may only appear once"),
+            ViolationKind::NotContiguous => write!(f, "IDAT chunks must be consecutive"),
+            ViolationKind::MisplacedBefore(other) => write!(f, "must appear before {}", other),
+            ViolationKind::MisplacedAfter(other) => write!(f, "must appear after {}", other),
+            ViolationKind::UnexpectedPalette => {
+                write!(f, "must not appear in grayscale images")
+            }
+            ViolationKind::UnknownCritical => write!(f, "unknown critical chunk"),
+            ViolationKind::ReservedBit => write!(f, "reserved bit of the chunk type is set"),
+            ViolationKind::Conflicting(other) => write!(f, "must not appear with {}", other),
+        }
+    }
+}
+
+/// Checks `chunks` against the chunk ordering rules of the PNG specification
+/// and returns every violation found, in chunk order.
+pub fn validate(chunks: &[Chunk]) -> Vec<Violation> {
+    let types: Vec<String> = chunks
+        .iter()
+        .map(|chunk| chunk.chunk_type().to_string())
+        .collect();
+    let position = |name: &str| types.iter().position(|chunk_type| chunk_type == name);
+    let first_plte = position("PLTE");
+    let first_idat = position("IDAT");
+    let first_iend = position("IEND");
+    let ihdr = chunks.first().and_then(|chunk| Ihdr::try_from(chunk).ok());
+
+    let mut violations = Vec::new();
+    let mut violation = |index: usize, chunk_type: &str, kind: ViolationKind| {
+        violations.push(Violation {
+            index,
+            chunk_type: ChunkType::from_str(chunk_type).unwrap(),
+            kind,
+        })
+    };
+
+    if position("IHDR").is_none() {
+        violation(0, "IHDR", ViolationKind::MissingChunk);
+    }
+    if first_idat.is_none() {
+        violation(
+            first_iend.unwrap_or(chunks.len()),
+            "IDAT",
+            ViolationKind::MissingChunk,
+        );
+    }
+    if let (Some(ihdr), None) = (ihdr, first_plte) {
+        if ihdr.color_type() == ColorType::Indexed {
+            violation(first_idat.unwrap_or(1), "PLTE", ViolationKind::MissingChunk);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut previous = None;
+    for (index, chunk) in chunks.iter().enumerate() {
+        let chunk_type = types[index].as_str();
+        let name = |names: &[&'static str]| names.iter().copied().find(|n| *n == chunk_type);
+
+        if !chunk.chunk_type().is_reserved_bit_valid() {
+            violation(index, chunk_type, ViolationKind::ReservedBit);
+        }
+        if chunk.chunk_type().is_critical() && name(&CRITICAL).is_none() {
+            violation(index, chunk_type, ViolationKind::UnknownCritical);
+        }
+        if first_iend.is_some_and(|end| index > end) {
+            violation(index, chunk_type, ViolationKind::AfterEnd);
+        }
+        if name(&UNIQUE).is_some() && !seen.insert(chunk_type) {
+            violation(index, chunk_type, ViolationKind::Duplicate);
+        }
+
+        match chunk_type {
+            "IHDR" if index != 0 => violation(index, chunk_type, ViolationKind::NotFirst),
+            "IEND" if index != chunks.len() - 1 && first_iend == Some(index) => {
+                violation(index, chunk_type, ViolationKind::NotLast)
+            }
+            "IDAT" if previous != Some("IDAT") && first_idat != Some(index) => {
+                violation(index, chunk_type, ViolationKind::NotContiguous)
+            }
+            "PLTE" => {
+                if first_idat.is_some_and(|idat| index > idat) {
+                    violation(index, chunk_type, ViolationKind::MisplacedBefore("IDAT"));
+                }
+                if ihdr.is_some_and(|ihdr| {
+                    matches!(
+                        ihdr.color_type(),
+                        ColorType::Grayscale | ColorType::GrayscaleAlpha
+                    )
+                }) {
+                    violation(index, chunk_type, ViolationKind::UnexpectedPalette);
+                }
+            }
+            _ => {}
+        }
+
+        if name(&BEFORE_PLTE).is_some() && first_plte.is_some_and(|plte| index > plte) {
+            violation(index, chunk_type, ViolationKind::MisplacedBefore("PLTE"));
+        }
+        if name(&AFTER_PLTE).is_some() && first_plte.is_some_and(|plte| index < plte) {
+            violation(index, chunk_type, ViolationKind::MisplacedAfter("PLTE"));
+        }
+        if name(&BEFORE_PLTE)
+            .or(name(&AFTER_PLTE))
+            .or(name(&BEFORE_IDAT))
+            .is_some()
+            && first_idat.is_some_and(|idat| index > idat)
+        {
+            violation(index, chunk_type, ViolationKind::MisplacedBefore("IDAT"));
+        }
+        if chunk_type == "iCCP" && position("sRGB").is_some() {
+            violation(index, chunk_type, ViolationKind::Conflicting("sRGB"));
+        }
+
+        previous = Some(chunk_type);
+    }
+
+    if first_iend.is_none() {
+        violation(chunks.len(), "IEND", ViolationKind::MissingChunk);
+    }
+
+    violations.sort_by_key(|violation| violation.index);
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::InterlaceMethod;
+
+    fn chunk(chunk_type: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), Vec::new())
+    }
+
+    fn header(color_type: ColorType) -> Chunk {
+        Ihdr::new(1, 1, 8, color_type, InterlaceMethod::None)
+            .unwrap()
+            .as_chunk()
+    }
+
+    fn kinds(chunks: &[Chunk]) -> Vec<(usize, String, ViolationKind)> {
+        validate(chunks)
+            .into_iter()
+            .map(|violation| {
+                (
+                    violation.index(),
+                    violation.chunk_type().to_string(),
+                    violation.kind().clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_chunks() {
+        let chunks = vec![
+            header(ColorType::Indexed),
+            chunk("gAMA"),
+            chunk("PLTE"),
+            chunk("tRNS"),
+            chunk("IDAT"),
+            chunk("IDAT"),
+            chunk("tIME"),
+            chunk("ruSt"),
+            chunk("IEND"),
+        ];
+        assert!(validate(&chunks).is_empty());
+    }
+
+    #[test]
+    fn test_missing_chunks() {
+        assert_eq!(
+            kinds(&[chunk("ruSt")]),
+            [
+                (0, "IHDR".to_string(), ViolationKind::MissingChunk),
+                (1, "IDAT".to_string(), ViolationKind::MissingChunk),
+                (1, "IEND".to_string(), ViolationKind::MissingChunk),
+            ]
+        );
+        assert_eq!(
+            kinds(&[header(ColorType::Indexed), chunk("IDAT"), chunk("IEND")]),
+            [(1, "PLTE".to_string(), ViolationKind::MissingChunk)]
+        );
+    }
+
+    #[test]
+    fn test_chunk_after_iend() {
+        let chunks = vec![
+            header(ColorType::Truecolor),
+            chunk("IDAT"),
+            chunk("IEND"),
+            chunk("ruSt"),
+        ];
+        assert_eq!(
+            kinds(&chunks),
+            [
+                (2, "IEND".to_string(), ViolationKind::NotLast),
+                (3, "ruSt".to_string(), ViolationKind::AfterEnd),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ihdr_not_first() {
+        let chunks = vec![
+            chunk("gAMA"),
+            header(ColorType::Truecolor),
+            chunk("IDAT"),
+            chunk("IEND"),
+        ];
+        assert_eq!(
+            kinds(&chunks),
+            [(1, "IHDR".to_string(), ViolationKind::NotFirst)]
+        );
+    }
+
+    #[test]
+    fn test_multiple_plte() {
+        let chunks = vec![
+            header(ColorType::Indexed),
+            chunk("PLTE"),
+            chunk("PLTE"),
+            chunk("IDAT"),
+            chunk("IEND"),
+        ];
+        assert_eq!(
+            kinds(&chunks),
+            [(2, "PLTE".to_string(), ViolationKind::Duplicate)]
+        );
+    }
+
+    #[test]
+    fn test_non_contiguous_idat() {
+        let chunks = vec![
+            header(ColorType::Truecolor),
+            chunk("IDAT"),
+            chunk("tEXt"),
+            chunk("IDAT"),
+            chunk("IEND"),
+        ];
+        assert_eq!(
+            kinds(&chunks),
+            [(3, "IDAT".to_string(), ViolationKind::NotContiguous)]
+        );
+    }
+
+    #[test]
+    fn test_misplaced_ancillary_chunks() {
+        let chunks = vec![
+            header(ColorType::Indexed),
+            chunk("tRNS"),
+            chunk("PLTE"),
+            chunk("gAMA"),
+            chunk("IDAT"),
+            chunk("pHYs"),
+            chunk("IEND"),
+        ];
+        assert_eq!(
+            kinds(&chunks),
+            [
+                (1, "tRNS".to_string(), ViolationKind::MisplacedAfter("PLTE")),
+                (
+                    3,
+                    "gAMA".to_string(),
+                    ViolationKind::MisplacedBefore("PLTE")
+                ),
+                (
+                    5,
+                    "pHYs".to_string(),
+                    ViolationKind::MisplacedBefore("IDAT")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_palette_in_grayscale_image() {
+        let chunks = vec![
+            header(ColorType::Grayscale),
+            chunk("PLTE"),
+            chunk("IDAT"),
+            chunk("IEND"),
+        ];
+        assert_eq!(
+            kinds(&chunks),
+            [(1, "PLTE".to_string(), ViolationKind::UnexpectedPalette)]
+        );
+    }
+
+    #[test]
+    fn test_unknown_critical_and_reserved_bit() {
+        let chunks = vec![
+            header(ColorType::Truecolor),
+            chunk("RuSt"),
+            chunk("rust"),
+            chunk("IDAT"),
+            chunk("IEND"),
+        ];
+        assert_eq!(
+            kinds(&chunks),
+            [
+                (1, "RuSt".to_string(), ViolationKind::UnknownCritical),
+                (2, "rust".to_string(), ViolationKind::ReservedBit),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_violation_display() {
+        let violations = validate(&[
+            header(ColorType::Truecolor),
+            chunk("IDAT"),
+            chunk("IEND"),
+            chunk("ruSt"),
+        ]);
+        assert_eq!(
+            violations[1].to_string(),
+            "chunk 3 (ruSt): appears after IEND"
+        );
+    }
+}
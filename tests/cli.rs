@@ -0,0 +1,165 @@
+//! Runs the CLI on `image/dice.png` and checks what it prints.
+
+use std::{path::Path, process::Command};
+
+fn image() -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("image/dice.png")
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// A scratch path for an output image, unique to this test run.
+fn scratch(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("steganography-cli-{}-{}", std::process::id(), name))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Runs the CLI, which must succeed, and returns its stdout and stderr.
+fn run(args: &[&str]) -> (String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_steganography"))
+        .args(args)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(output.status.success(), "{:?} failed: {}", args, stderr);
+    (stdout, stderr)
+}
+
+#[test]
+fn test_text_message_is_readable() {
+    let output = scratch("comment.png");
+    for format in ["text", "ztxt", "itxt"] {
+        let (_, stderr) = run(&[
+            "encode",
+            &image(),
+            "ruSt",
+            "secret msg",
+            &output,
+            "--as",
+            format,
+            "--keyword",
+            "Comment",
+        ]);
+        assert!(stderr.is_empty(), "{}", stderr);
+        let (stdout, _) = run(&["print", &output]);
+        assert!(stdout.contains("Comment: secret msg"), "{}", stdout);
+    }
+    let _ = std::fs::remove_file(output);
+}
+
+#[test]
+fn test_text_message_with_keyword_in_use() {
+    // dice.png already has a tEXt chunk with the keyword Software.
+    let output = scratch("software.png");
+    for format in ["text", "ztxt", "itxt"] {
+        let (_, stderr) = run(&[
+            "encode",
+            &image(),
+            "ruSt",
+            "secret msg",
+            &output,
+            "--as",
+            format,
+            "--keyword",
+            "Software",
+        ]);
+        assert!(stderr.contains("already has text with the keyword Software"));
+        let (stdout, _) = run(&["print", &output]);
+        assert!(stdout.contains("Software: U3RQ"), "{}", stdout);
+
+        let (stdout, _) = run(&[
+            "decode",
+            &output,
+            "ruSt",
+            "--as",
+            format,
+            "--keyword",
+            "Software",
+        ]);
+        assert_eq!(stdout, "secret msg\n", "{}", format);
+    }
+    let _ = std::fs::remove_file(output);
+}
+
+#[test]
+fn test_capacity() {
+    let (stdout, _) = run(&["capacity", &image()]);
+    let row = |mode: &str| {
+        stdout
+            .lines()
+            .find(|line| line.starts_with(mode))
+            .unwrap_or_else(|| panic!("no {} row in {}", mode, stdout))
+            .split_whitespace()
+            .skip(mode.split_whitespace().count())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    assert_eq!(row("chunk"), "unlimited");
+    assert_eq!(row("deflate"), "unlimited");
+    let plain = 671 * 448 * 3 / 8 - 4;
+    assert!(row("lsb --bits 1").starts_with(&plain.to_string()));
+}
+
+#[test]
+fn test_capacity_with_code() {
+    let (stdout, _) = run(&["capacity", &image(), "--code", "3"]);
+    let row = stdout
+        .lines()
+        .find(|line| line.starts_with("lsb --bits 1 --code 3"))
+        .unwrap_or_else(|| panic!("no coded row in {}", stdout));
+    // Three bits in every seven samples, after the 4 byte length.
+    let coded = 671 * 448 * 3 / 7 * 3 / 8 - 4;
+    let plain: usize = row.split_whitespace().nth(5).unwrap().parse().unwrap();
+    assert_eq!(plain, coded);
+}
+
+#[test]
+fn test_rewrite_leaves_other_files_alone() {
+    let output = scratch("rewrite.png");
+    let neighbour = format!("{}.tmp", output);
+    std::fs::write(&neighbour, b"keep me").unwrap();
+    run(&["encode", &image(), "ruSt", "message", &output]);
+    run(&["remove", &output, "ruSt"]);
+    assert_eq!(std::fs::read(&neighbour).unwrap(), b"keep me");
+
+    let dir = std::env::temp_dir();
+    let prefix = format!(".steganography-cli-{}-rewrite.png.", std::process::id());
+    let leftovers = std::fs::read_dir(dir)
+        .unwrap()
+        .filter(|entry| {
+            let name = entry.as_ref().unwrap().file_name();
+            name.to_string_lossy().starts_with(&prefix)
+        })
+        .count();
+    assert_eq!(leftovers, 0);
+    let _ = std::fs::remove_file(output);
+    let _ = std::fs::remove_file(neighbour);
+}
+
+#[test]
+fn test_decode_without_chunk_type() {
+    let output = scratch("lsb.png");
+    run(&[
+        "encode",
+        &image(),
+        "ruSt",
+        "in the pixels",
+        &output,
+        "--mode",
+        "lsb",
+    ]);
+    let (stdout, _) = run(&["decode", &output, "--mode", "lsb"]);
+    assert_eq!(stdout, "in the pixels\n");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_steganography"))
+        .args(["decode", &output])
+        .output()
+        .unwrap()
+        .status;
+    assert!(!status.success(), "chunk mode needs a chunk type");
+    let _ = std::fs::remove_file(output);
+}
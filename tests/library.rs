@@ -0,0 +1,86 @@
+//! Uses the library the way a dependent crate would, through its public API only.
+
+use std::str::FromStr;
+
+use steganography::{
+    crypto,
+    ihdr::{ColorType, Ihdr, InterlaceMethod},
+    lsb::{self, LsbOptions},
+    png::Placement,
+    stream::{ChunkReader, ChunkWriter},
+    Chunk, ChunkType, Error, Png,
+};
+
+const PNG_FILE: &[u8] = include_bytes!("../image/dice.png");
+
+#[test]
+fn test_parse_file() {
+    let png = Png::try_from(PNG_FILE).unwrap();
+    assert_eq!(
+        png.ihdr().unwrap().to_string(),
+        "671x448, 8-bit truecolor, non-interlaced"
+    );
+    assert!(png.validate().is_empty());
+    assert_eq!(png.as_bytes(), PNG_FILE);
+}
+
+#[test]
+fn test_build_file() {
+    let ihdr = Ihdr::new(1, 1, 8, ColorType::Grayscale, InterlaceMethod::None).unwrap();
+    let mut png = Png::from_chunks(vec![
+        ihdr.as_chunk(),
+        Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+    ]);
+    png.replace_image_data(&[120, 156, 99, 0, 0, 0, 1, 0, 1]);
+    assert!(png.validate().is_empty());
+
+    let parsed = Png::try_from(png.as_bytes().as_ref()).unwrap();
+    assert_eq!(parsed.ihdr().unwrap(), ihdr);
+    assert_eq!(parsed.chunks().len(), 3);
+}
+
+#[test]
+fn test_chunk_message() {
+    let mut png = Png::try_from(PNG_FILE).unwrap();
+    let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hidden".to_vec());
+    png.insert_chunk(chunk, Placement::BeforeEnd);
+
+    let png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+    assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"hidden");
+    assert_eq!(
+        png.chunks().last().unwrap().chunk_type().to_string(),
+        "IEND"
+    );
+}
+
+#[test]
+fn test_encrypted_pixel_message() {
+    let mut png = Png::try_from(PNG_FILE).unwrap();
+    let payload = crypto::encrypt(b"password", b"hidden").unwrap();
+    lsb::embed(&mut png, &payload, &LsbOptions::default()).unwrap();
+
+    let png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+    let payload = lsb::extract(&png, &LsbOptions::default()).unwrap();
+    assert!(crypto::is_encrypted(&payload));
+    assert_eq!(crypto::decrypt(b"password", &payload).unwrap(), b"hidden");
+    assert!(matches!(
+        crypto::decrypt(b"wrong", &payload),
+        Err(Error::DecryptionFailed)
+    ));
+}
+
+#[test]
+fn test_stream_file() {
+    let mut writer = ChunkWriter::new(Vec::new()).unwrap();
+    for chunk in ChunkReader::new(PNG_FILE).unwrap() {
+        writer.write_chunk(&chunk.unwrap()).unwrap();
+    }
+    assert_eq!(writer.finish().unwrap(), PNG_FILE);
+}
+
+#[test]
+fn test_malformed_file() {
+    let error = Png::try_from(&PNG_FILE[..PNG_FILE.len() - 1]).unwrap_err();
+    assert!(matches!(error, Error::TruncatedChunk { .. }));
+    assert_eq!(error.exit_code(), 21);
+}
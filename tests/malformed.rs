@@ -0,0 +1,80 @@
+//! Runs every command over the files in `tests/corpus`, each of which is
+//! broken in a different way, and checks that the CLI reports an error or
+//! succeeds instead of panicking.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const PANIC_EXIT_CODE: i32 = 101;
+
+fn corpus() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    files.sort();
+    assert!(!files.is_empty());
+    files
+}
+
+fn run(args: &[&str]) {
+    let output = Command::new(env!("CARGO_BIN_EXE_steganography"))
+        .args(args)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_ne!(
+        output.status.code(),
+        Some(PANIC_EXIT_CODE),
+        "{:?} panicked: {}",
+        args,
+        stderr
+    );
+    assert!(
+        !stderr.contains("panicked"),
+        "{:?} panicked: {}",
+        args,
+        stderr
+    );
+}
+
+/// Copies a corpus file to a scratch location so commands that rewrite their
+/// input leave the corpus untouched.
+fn scratch_copy(path: &Path, suffix: &str) -> String {
+    let name = path.file_name().unwrap().to_string_lossy();
+    let copy = std::env::temp_dir().join(format!(
+        "steganography-{}-{}-{}",
+        std::process::id(),
+        suffix,
+        name
+    ));
+    fs::copy(path, &copy).unwrap();
+    copy.to_string_lossy().into_owned()
+}
+
+#[test]
+fn test_read_commands_do_not_panic() {
+    for path in corpus() {
+        let path = path.to_str().unwrap();
+        run(&["print", path]);
+        run(&["validate", path]);
+        run(&["validate", "--strict", path]);
+        run(&["decode", path, "ruSt"]);
+        run(&["decode", "--mode", "lsb", path, "ruSt"]);
+    }
+}
+
+#[test]
+fn test_write_commands_do_not_panic() {
+    for path in corpus() {
+        let copy = scratch_copy(&path, "write");
+        run(&["encode", &copy, "ruSt", "message"]);
+        run(&["encode", "--mode", "lsb", &copy, "ruSt", "message"]);
+        run(&["remove", &copy, "ruSt"]);
+        let _ = fs::remove_file(&copy);
+    }
+}